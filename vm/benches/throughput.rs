@@ -0,0 +1,37 @@
+//! Measure the number of instructions executed per second by the
+//! interpreter. Run with `cargo bench`.
+
+use interpreter::Machine;
+use std::hint::black_box;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Minimal duration of the measure for each program.
+const DURATION: Duration = Duration::from_secs(2);
+
+/// Run `program` repeatedly, with `r10` set to `arg`, and print the
+/// achieved throughput.
+fn bench(name: &str, program: &[u8], arg: u32) {
+    let mut steps = 0;
+    let start = Instant::now();
+    while start.elapsed() < DURATION {
+        let mut machine = Machine::new(program).unwrap();
+        machine.set_reg(10, arg).unwrap();
+        machine.run_on(&mut io::sink()).unwrap();
+        steps += black_box(machine.steps());
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{name:<20} {steps:>12} instructions in {elapsed:.2}s: {:>8.2} Minstr/s",
+        steps as f64 / elapsed / 1e6
+    );
+}
+
+fn main() {
+    bench(
+        "examples/99bottles",
+        include_bytes!("../examples/99bottles.bin"),
+        0,
+    );
+    bench("tests/rfact", include_bytes!("../tests/rfact.bin"), 12);
+}
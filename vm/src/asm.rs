@@ -0,0 +1,1137 @@
+use crate::{
+    BinaryOp, Condition, Instruction, MEMORY_SIZE, Object, Relocation, RelocationKind, Section,
+    SectionKind,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Error raised while assembling a listing.
+#[derive(Debug)]
+pub struct AsmError {
+    /// Line of the source (starting at 1) where the error was found
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Labels defined by a listing, with their address.
+pub type Labels = BTreeMap<String, usize>;
+
+/// Immediate operand, a constant plus or minus labels which may be defined
+/// anywhere in the listing (`#str_1+4`).
+#[derive(Default)]
+struct Imm {
+    value: i64,
+    /// Labels with their sign (1 or -1)
+    labels: Vec<(i64, String)>,
+}
+
+impl Imm {
+    /// Value of the immediate, once all labels are defined.
+    fn resolve(&self, labels: &Labels) -> Result<i64, String> {
+        let mut value = self.value;
+        for (sign, name) in &self.labels {
+            let address = labels
+                .get(name)
+                .ok_or_else(|| format!("undefined label `{name}`"))?;
+            value += sign * *address as i64;
+        }
+        Ok(value)
+    }
+
+    /// Symbol and addend of the relocation needed by the immediate when the
+    /// sections move, or `None` if its value does not depend on where they
+    /// are. `symbols` are the labels defined by the listing, the others
+    /// being defined by another object file.
+    fn relocation(&self, symbols: &Labels) -> Result<Option<(String, i64)>, String> {
+        let offset: i64 = self.labels.iter().map(|(sign, _)| sign).sum();
+        let externals: Vec<_> = (self.labels.iter())
+            .filter(|(_, name)| !symbols.contains_key(name))
+            .collect();
+        let symbol = match (offset, externals.as_slice()) {
+            (0, []) => return Ok(None),
+            (1, []) => self.labels.iter().find(|(sign, _)| *sign == 1),
+            (1, [external @ (1, _)]) => Some(*external),
+            _ => None,
+        };
+        let Some((_, symbol)) = symbol else {
+            return Err("expression cannot be relocated".to_owned());
+        };
+        // Undefined labels are external symbols, resolved by the linker
+        let mut labels = symbols.clone();
+        for (_, name) in externals {
+            labels.insert(name.clone(), 0);
+        }
+        Ok(Some((
+            symbol.clone(),
+            self.resolve(&labels)? - labels[symbol] as i64,
+        )))
+    }
+}
+
+/// One line of the listing, once parsed.
+enum Statement {
+    Instruction(Instruction),
+    /// `loadimm` whose immediate is only known once all labels are defined
+    LoadImm(u8, Imm),
+    /// `call` whose target is only known once all labels are defined
+    Call(Imm),
+    /// Branch whose offset is only known once all labels are defined
+    Branch(Condition, u8, u8, Imm),
+    /// Instructions a pseudo-instruction expands to
+    Pseudo(Vec<Instruction>),
+    Data(Vec<u8>),
+    /// Zeroes of `.space` and `.align`
+    Space(usize),
+    /// Little-endian 32-bit words of `.word`, which may refer to labels
+    Words(Vec<Imm>),
+}
+
+impl Statement {
+    /// Number of bytes taken by the statement in memory.
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction(instruction) => instruction.size(),
+            Statement::LoadImm(..) => 4,
+            Statement::Call(..) => 3,
+            Statement::Branch(..) => 5,
+            Statement::Pseudo(instructions) => instructions.iter().map(Instruction::size).sum(),
+            Statement::Data(bytes) => bytes.len(),
+            Statement::Space(size) => *size,
+            Statement::Words(words) => 4 * words.len(),
+        }
+    }
+
+    /// Whether the statement holds data rather than instructions.
+    fn is_data(&self) -> bool {
+        matches!(
+            self,
+            Statement::Data(_) | Statement::Space(_) | Statement::Words(_)
+        )
+    }
+
+    /// Append the encoded statement, found at `address`, to `out`,
+    /// resolving labels.
+    fn emit(&self, labels: &Labels, address: usize, out: &mut Vec<u8>) -> Result<(), String> {
+        match self {
+            Statement::Instruction(instruction) => out.extend(instruction.encode()),
+            Statement::LoadImm(rd, imm) => {
+                let value = imm.resolve(labels)?;
+                let imm = i16::try_from(value)
+                    .map_err(|_| format!("immediate {value} does not fit in 16 signed bits"))?;
+                out.extend(Instruction::LoadImm { rd: *rd, imm }.encode());
+            }
+            Statement::Call(imm) => {
+                let value = imm.resolve(labels)?;
+                let target = u16::try_from(value)
+                    .map_err(|_| format!("call target {value} does not fit in 16 bits"))?;
+                out.extend(Instruction::Call { target }.encode());
+            }
+            Statement::Branch(cond, rs1, rs2, imm) => {
+                // Labels designate the target, numbers the offset itself
+                let value = if imm.labels.is_empty() {
+                    imm.value
+                } else {
+                    imm.resolve(labels)? - address as i64
+                };
+                let offset = i16::try_from(value)
+                    .map_err(|_| format!("branch offset {value} does not fit in 16 signed bits"))?;
+                out.extend(
+                    Instruction::Branch {
+                        cond: *cond,
+                        rs1: *rs1,
+                        rs2: *rs2,
+                        offset,
+                    }
+                    .encode(),
+                );
+            }
+            Statement::Pseudo(instructions) => {
+                out.extend(instructions.iter().flat_map(Instruction::encode));
+            }
+            Statement::Data(bytes) => out.extend(bytes),
+            Statement::Space(size) => out.resize(out.len() + size, 0),
+            Statement::Words(words) => {
+                for word in words {
+                    let value = word.resolve(labels)?;
+                    let word = u32::try_from(value)
+                        .or_else(|_| i32::try_from(value).map(|value| value as u32))
+                        .map_err(|_| format!("{value} does not fit in 32 bits"))?;
+                    out.extend(word.to_le_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Assemble a listing written in the format of the `.dis` files into a
+/// memory image suitable for [`Machine::new`](crate::Machine::new).
+///
+/// Lines may start with an address column (`0012` or `????`), which is
+/// ignored. Labels are written `name:`, immediates `#42`, `#-4` or `#name`,
+/// and data either as a byte string (`b'Hello\n'`) or as a list of bytes
+/// (`[0, 0, 0, 0]`). A `;` starts a comment running to the end of the line.
+/// Branches written with a label (`beq r1, r2, #loop`) jump to the label,
+/// while a number (`#-8`) is the offset from the branch itself.
+///
+/// Immediates may add or subtract numbers, labels and constants
+/// (`#message+4`). Directives start with a dot:
+/// - `.equ name, value` defines a constant;
+/// - `.include "path"` assembles another listing in place, the path being
+///   relative to the directory of the including listing (see
+///   [`Assembler::include_dir`] for the top-level one);
+/// - `.byte`, `.word` (little-endian 32-bit words, which may hold
+///   addresses) and `.string` or `.asciz` (with a final 0 byte) add data,
+///   and `.space size` zeroes;
+/// - `.align size` pads with zeroes up to a multiple of `size`, and
+///   `.org address` continues the listing at `address`;
+/// - `.macro name param, ...` starts the definition of a macro, ending at
+///   `.endm`, which is then invoked as `name arg, ...`. Its body refers to
+///   the parameters as `\param`, and to a number unique to the expansion
+///   as `\@`, to build distinct labels.
+///
+/// The pseudo-instruction `li rd, value` loads any 32-bit value. Values
+/// which do not fit in the immediate of `loadimm` take several instructions,
+/// using r3 as a scratch register (r4 when loading r3).
+///
+/// # Errors
+/// An error is returned, with the offending line, on syntax errors,
+/// duplicate or undefined labels, out-of-range values, overlapping
+/// statements and images exceeding [`MEMORY_SIZE`].
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    Assembler::new().assemble(source)
+}
+
+/// Similar to [`assemble`], but also return the labels defined by the
+/// listing, so that tools can refer to addresses by name.
+///
+/// # Errors
+/// See [`assemble`].
+pub fn assemble_with_labels(source: &str) -> Result<(Vec<u8>, Labels), AsmError> {
+    Assembler::new().assemble_with_labels(source)
+}
+
+/// Similar to [`assemble`], but produce an [`Object`] whose sections are
+/// the runs of instructions and of data of the listing, and whose symbols
+/// are its labels. Execution starts at the `_start` label if it is defined,
+/// and at address 0 otherwise.
+///
+/// Labels used by `loadimm` and `call` instructions get a relocation, so
+/// that the object can be [linked](crate::link). These labels may be left
+/// undefined, to be defined by another object file.
+///
+/// # Errors
+/// See [`assemble`].
+pub fn assemble_object(source: &str) -> Result<Object, AsmError> {
+    Assembler::new().assemble_object(source)
+}
+
+/// Line numbers (starting at 1) of the instructions of a listing, along
+/// with their address. Data lines are not included.
+///
+/// # Errors
+/// See [`assemble`].
+pub fn instruction_lines(source: &str) -> Result<Vec<(usize, usize)>, AsmError> {
+    Assembler::new().instruction_lines(source)
+}
+
+/// Assembler of listings found in a given directory, against which the
+/// paths of their `.include` directives are resolved.
+#[derive(Clone, Debug, Default)]
+pub struct Assembler {
+    include_dir: PathBuf,
+}
+
+impl Assembler {
+    /// Assembler resolving `.include` paths against the current directory.
+    #[must_use]
+    pub fn new() -> Self {
+        Assembler::default()
+    }
+
+    /// Resolve the `.include` paths of the listing against `dir`, usually
+    /// the directory the listing comes from.
+    #[must_use]
+    pub fn include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dir = dir.into();
+        self
+    }
+
+    /// See [`assemble`].
+    ///
+    /// # Errors
+    /// See [`assemble`].
+    pub fn assemble(&self, source: &str) -> Result<Vec<u8>, AsmError> {
+        self.assemble_with_labels(source).map(|(image, _)| image)
+    }
+
+    /// See [`assemble_with_labels`].
+    ///
+    /// # Errors
+    /// See [`assemble`].
+    pub fn assemble_with_labels(&self, source: &str) -> Result<(Vec<u8>, Labels), AsmError> {
+        let (labels, statements) = self.parse(source)?;
+        Ok((emit(&labels, &statements)?, labels))
+    }
+
+    /// See [`assemble_object`].
+    ///
+    /// # Errors
+    /// See [`assemble`].
+    pub fn assemble_object(&self, source: &str) -> Result<Object, AsmError> {
+        let (symbols, statements) = self.parse(source)?;
+        let mut labels = symbols.clone();
+        let mut relocations = Vec::new();
+        for (line, address, statement) in &statements {
+            let fields = match statement {
+                Statement::LoadImm(_, imm) => vec![(*address, RelocationKind::LoadImm, imm)],
+                Statement::Call(imm) => vec![(*address, RelocationKind::Call, imm)],
+                Statement::Words(words) => (words.iter().enumerate())
+                    .map(|(index, imm)| (address + 4 * index, RelocationKind::Word, imm))
+                    .collect(),
+                _ => continue,
+            };
+            let line_error = |message| AsmError {
+                line: *line,
+                message,
+            };
+            for (address, kind, imm) in fields {
+                let Some((symbol, addend)) = imm.relocation(&symbols).map_err(line_error)? else {
+                    continue;
+                };
+                let addend = i32::try_from(addend)
+                    .map_err(|_| line_error(format!("addend {addend} does not fit in 32 bits")))?;
+                // Undefined labels are external symbols, resolved by the linker
+                for (_, name) in &imm.labels {
+                    labels.entry(name.clone()).or_insert(0);
+                }
+                relocations.push(Relocation {
+                    address,
+                    kind,
+                    symbol,
+                    addend,
+                });
+            }
+        }
+        let image = emit(&labels, &statements)?;
+
+        let mut sections: Vec<Section> = Vec::new();
+        for (_, address, statement) in &statements {
+            let kind = if statement.is_data() {
+                SectionKind::Data
+            } else {
+                SectionKind::Code
+            };
+            let bytes = &image[*address..address + statement.size()];
+            match sections.last_mut() {
+                Some(section) if section.kind == kind && section.end() == *address => {
+                    section.bytes.extend_from_slice(bytes);
+                }
+                _ => sections.push(Section {
+                    kind,
+                    address: *address,
+                    bytes: bytes.to_vec(),
+                }),
+            }
+        }
+        Ok(Object {
+            entry: symbols.get("_start").copied().unwrap_or(0),
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+
+    /// See [`instruction_lines`].
+    ///
+    /// # Errors
+    /// See [`assemble`].
+    pub fn instruction_lines(&self, source: &str) -> Result<Vec<(usize, usize)>, AsmError> {
+        let (_, statements) = self.parse(source)?;
+        Ok(statements
+            .into_iter()
+            .filter(|(_, _, statement)| !statement.is_data())
+            .map(|(line, address, _)| (line, address))
+            .collect())
+    }
+
+    // Collect the labels and the statements of a listing
+    fn parse(&self, source: &str) -> Result<(Labels, Vec<Located>), AsmError> {
+        let mut parser = Parser::default();
+        parser.source(source, &self.include_dir, None, 0)?;
+        parser.check_layout()?;
+        Ok((parser.labels, parser.statements))
+    }
+}
+
+// Encode the statements of a listing
+fn emit(labels: &Labels, statements: &[Located]) -> Result<Vec<u8>, AsmError> {
+    let size = (statements.iter())
+        .map(|(_, address, statement)| address + statement.size())
+        .max()
+        .unwrap_or(0);
+    // Gaps left by `.org` are filled with zeroes
+    let mut image = vec![0; size];
+    let mut bytes = Vec::new();
+    for (line, address, statement) in statements {
+        bytes.clear();
+        statement
+            .emit(labels, *address, &mut bytes)
+            .map_err(|message| AsmError {
+                line: *line,
+                message,
+            })?;
+        image[*address..address + bytes.len()].copy_from_slice(&bytes);
+    }
+    Ok(image)
+}
+
+/// Statement with its line and address.
+type Located = (usize, usize, Statement);
+
+/// Values of the constants defined with `.equ`.
+type Constants = BTreeMap<String, i64>;
+
+/// Macro defined by `.macro name param, ...` up to `.endm`.
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// Limit on nested includes and macro expansions, which stops recursive
+/// ones.
+const MAX_NESTING: usize = 64;
+
+/// Error reported when [`MAX_NESTING`] is reached.
+const TOO_DEEP: &str = "too many nested includes or macros";
+
+/// State of the parser, shared by the listing, the files it includes and
+/// the macros it expands.
+#[derive(Default)]
+struct Parser {
+    labels: Labels,
+    constants: Constants,
+    macros: BTreeMap<String, Macro>,
+    statements: Vec<Located>,
+    address: usize,
+    // Macro expansions so far, numbering the expansions for `\@`
+    expansions: usize,
+}
+
+impl Parser {
+    /// Parse the lines of `source`, found in directory `dir`. The
+    /// statements of included files and of macro expansions are attributed
+    /// to the line of the listing (`origin`) which includes or expands them.
+    fn source(
+        &mut self,
+        source: &str,
+        dir: &Path,
+        origin: Option<usize>,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let mut lines = source.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let line_error = |message| AsmError {
+                line: index + 1,
+                message,
+            };
+            let origin = origin.unwrap_or(index + 1);
+            let mut cursor = Cursor::new(line);
+            cursor.skip_address();
+            while let Some(label) = cursor.label() {
+                if self.constants.contains_key(label)
+                    || self.labels.insert(label.to_owned(), self.address).is_some()
+                {
+                    return Err(line_error(format!("duplicate label `{label}`")));
+                }
+            }
+            if cursor.at_end() {
+                continue;
+            }
+            if cursor.eat(".") {
+                match cursor.ident().unwrap_or_default() {
+                    "macro" => {
+                        let (name, params) = cursor.macro_signature().map_err(line_error)?;
+                        let mut body = Vec::new();
+                        loop {
+                            let Some((_, line)) = lines.next() else {
+                                return Err(line_error("missing `.endm`".to_owned()));
+                            };
+                            if Cursor::new(line).eat(".endm") {
+                                break;
+                            }
+                            body.push(line.to_owned());
+                        }
+                        if self
+                            .macros
+                            .insert(name.clone(), Macro { params, body })
+                            .is_some()
+                        {
+                            return Err(line_error(format!("duplicate macro `{name}`")));
+                        }
+                    }
+                    "equ" => {
+                        let name = (cursor.ident())
+                            .ok_or_else(|| line_error("expected a constant name".to_owned()))?;
+                        cursor.expect(",").map_err(line_error)?;
+                        let value = cursor.constant(&self.constants).map_err(line_error)?;
+                        cursor.end().map_err(line_error)?;
+                        if self.labels.contains_key(name)
+                            || self.constants.insert(name.to_owned(), value).is_some()
+                        {
+                            return Err(line_error(format!("duplicate constant `{name}`")));
+                        }
+                    }
+                    "include" => {
+                        let path = cursor.string().map_err(line_error)?;
+                        cursor.end().map_err(line_error)?;
+                        let file = dir.join(path);
+                        let text = std::fs::read_to_string(&file)
+                            .map_err(|e| line_error(format!("cannot include `{path}`: {e}")))?;
+                        let file_dir = file.parent().unwrap_or(dir);
+                        let context = format!("in `{path}`");
+                        self.nested(&text, file_dir, origin, depth, index + 1, &context)?;
+                    }
+                    "org" => {
+                        let address = cursor.constant(&self.constants).map_err(line_error)?;
+                        cursor.end().map_err(line_error)?;
+                        self.address = usize::try_from(address)
+                            .map_err(|_| line_error(format!("invalid address {address}")))?;
+                    }
+                    "align" => {
+                        let alignment = cursor.constant(&self.constants).map_err(line_error)?;
+                        cursor.end().map_err(line_error)?;
+                        let alignment = usize::try_from(alignment)
+                            .ok()
+                            .filter(|alignment| alignment.is_power_of_two())
+                            .ok_or_else(|| {
+                                line_error(format!("alignment {alignment} is not a power of two"))
+                            })?;
+                        let end = (self.address.checked_next_multiple_of(alignment))
+                            .unwrap_or(usize::MAX);
+                        self.push(origin, Statement::Space(end - self.address))
+                            .map_err(line_error)?;
+                    }
+                    directive => {
+                        let statement = parse_data(directive, &mut cursor, &self.constants)
+                            .map_err(line_error)?;
+                        self.push(origin, statement).map_err(line_error)?;
+                    }
+                }
+                continue;
+            }
+            if let Some(name) = cursor.macro_name(&self.macros) {
+                let expansion = self.expand(name, &mut cursor).map_err(line_error)?;
+                let context = format!("in macro `{name}`");
+                self.nested(&expansion, dir, origin, depth, index + 1, &context)?;
+                continue;
+            }
+            let statement = parse_statement(&mut cursor, &self.constants).map_err(line_error)?;
+            self.push(origin, statement).map_err(line_error)?;
+        }
+        Ok(())
+    }
+
+    /// Add a statement at the current address, provided that it fits in
+    /// memory.
+    fn push(&mut self, line: usize, statement: Statement) -> Result<(), String> {
+        let end = self.address.saturating_add(statement.size());
+        if end > MEMORY_SIZE {
+            return Err(format!(
+                "image of {end} bytes does not fit in memory ({MEMORY_SIZE} bytes)"
+            ));
+        }
+        self.statements.push((line, self.address, statement));
+        self.address = end;
+        Ok(())
+    }
+
+    /// Check that statements moved by `.org` do not overlap.
+    fn check_layout(&self) -> Result<(), AsmError> {
+        let mut regions: Vec<_> = (self.statements.iter())
+            .filter(|(_, _, statement)| statement.size() > 0)
+            .map(|(line, address, statement)| (*address, address + statement.size(), *line))
+            .collect();
+        regions.sort_unstable();
+        for pair in regions.windows(2) {
+            let [(_, end, first), (address, _, second)] = pair else {
+                unreachable!()
+            };
+            if address < end {
+                return Err(AsmError {
+                    line: *first.max(second),
+                    message: format!(
+                        "address {address} is already taken by line {}",
+                        first.min(second)
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse an included file or a macro expansion found at `line`, whose
+    /// errors are prefixed by `context`. Reaching the nesting limit is
+    /// reported once, at the line of the listing.
+    fn nested(
+        &mut self,
+        source: &str,
+        dir: &Path,
+        origin: usize,
+        depth: usize,
+        line: usize,
+        context: &str,
+    ) -> Result<(), AsmError> {
+        if depth == MAX_NESTING {
+            return Err(AsmError {
+                line: origin,
+                message: TOO_DEEP.to_owned(),
+            });
+        }
+        self.source(source, dir, Some(origin), depth + 1)
+            .map_err(|e| match e.message.as_str() {
+                TOO_DEEP => e,
+                _ => AsmError {
+                    line,
+                    message: format!("{context}, {e}"),
+                },
+            })
+    }
+
+    /// Body of macro `name` with the parameters replaced by the arguments
+    /// found by `cursor`, and `\@` by the number of the expansion.
+    fn expand(&mut self, name: &str, cursor: &mut Cursor) -> Result<String, String> {
+        let Macro { params, body } = &self.macros[name];
+        let args = cursor.macro_args();
+        if args.len() != params.len() {
+            return Err(format!(
+                "macro `{name}` expects {} arguments, found {}",
+                params.len(),
+                args.len()
+            ));
+        }
+        let mut expansion = String::new();
+        for line in body {
+            let mut rest = line.as_str();
+            while let Some(start) = rest.find('\\') {
+                expansion.push_str(&rest[..start]);
+                rest = &rest[start + 1..];
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if rest.starts_with('@') {
+                    expansion.push_str(&self.expansions.to_string());
+                    rest = &rest[1..];
+                } else if let Some(index) = params.iter().position(|param| *param == rest[..len]) {
+                    expansion.push_str(args[index]);
+                    rest = &rest[len..];
+                } else {
+                    expansion.push('\\');
+                }
+            }
+            expansion.push_str(rest);
+            expansion.push('\n');
+        }
+        self.expansions += 1;
+        Ok(expansion)
+    }
+}
+
+fn parse_statement(cursor: &mut Cursor, constants: &Constants) -> Result<Statement, String> {
+    if cursor.peek() == Some('[') {
+        let bytes = cursor.byte_list()?;
+        cursor.end()?;
+        return Ok(Statement::Data(bytes));
+    }
+    if let Some(bytes) = cursor.byte_string()? {
+        cursor.end()?;
+        return Ok(Statement::Data(bytes));
+    }
+    let mnemonic = cursor
+        .ident()
+        .ok_or_else(|| "expected an instruction".to_owned())?;
+    let instruction = match mnemonic {
+        "move" => {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            let rs = cursor.reg()?;
+            cursor.expect_word("if")?;
+            let rc = cursor.reg()?;
+            cursor.expect("!=")?;
+            cursor.expect("0")?;
+            Instruction::MoveIf { rd, rs, rc }
+        }
+        "store" => {
+            cursor.expect("[")?;
+            let ra = cursor.reg()?;
+            cursor.expect("]")?;
+            cursor.expect("<-")?;
+            let rs = cursor.reg()?;
+            Instruction::Store { ra, rs }
+        }
+        "load" => {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            cursor.expect("[")?;
+            let ra = cursor.reg()?;
+            cursor.expect("]")?;
+            Instruction::Load { rd, ra }
+        }
+        "loadimm" => {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            let imm = cursor.imm(constants)?;
+            cursor.end()?;
+            return Ok(Statement::LoadImm(rd, imm));
+        }
+        "li" => {
+            let rd = cursor.reg()?;
+            cursor.expect(",")?;
+            cursor.eat("#");
+            let imm = cursor.expr(constants)?;
+            cursor.end()?;
+            return load_constant(rd, imm);
+        }
+        "sub" => {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            let rs1 = cursor.reg()?;
+            cursor.expect("-")?;
+            let rs2 = cursor.reg()?;
+            Instruction::Sub { rd, rs1, rs2 }
+        }
+        "out" => Instruction::Out { rs: cursor.reg()? },
+        "exit" => Instruction::Exit,
+        "out_number" => Instruction::OutNumber { rs: cursor.reg()? },
+        "in" => Instruction::In { rd: cursor.reg()? },
+        "in_number" => Instruction::InNumber { rd: cursor.reg()? },
+        "push" => Instruction::Push { rs: cursor.reg()? },
+        "pop" => Instruction::Pop { rd: cursor.reg()? },
+        "call" => {
+            let target = cursor.imm(constants)?;
+            cursor.end()?;
+            return Ok(Statement::Call(target));
+        }
+        "ret" => Instruction::Ret,
+        "iret" => Instruction::Iret,
+        "not" => {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            cursor.expect("~")?;
+            let rs = cursor.reg()?;
+            Instruction::Not { rd, rs }
+        }
+        _ if let Some(op) = BinaryOp::ALL
+            .into_iter()
+            .find(|op| op.mnemonic() == mnemonic) =>
+        {
+            let rd = cursor.reg()?;
+            cursor.expect("<-")?;
+            let rs1 = cursor.reg()?;
+            cursor.expect(op.symbol())?;
+            let rs2 = cursor.reg()?;
+            Instruction::Binary { op, rd, rs1, rs2 }
+        }
+        _ if let Some(cond) = Condition::ALL
+            .into_iter()
+            .find(|c| c.mnemonic() == mnemonic) =>
+        {
+            let rs1 = cursor.reg()?;
+            cursor.expect(",")?;
+            let rs2 = cursor.reg()?;
+            cursor.expect(",")?;
+            let target = cursor.imm(constants)?;
+            cursor.end()?;
+            return Ok(Statement::Branch(cond, rs1, rs2, target));
+        }
+        _ => return Err(format!("unknown instruction `{mnemonic}`")),
+    };
+    cursor.end()?;
+    Ok(Statement::Instruction(instruction))
+}
+
+/// Data of the `.byte`, `.word`, `.string`, `.asciz` and `.space`
+/// directives.
+fn parse_data(
+    directive: &str,
+    cursor: &mut Cursor,
+    constants: &Constants,
+) -> Result<Statement, String> {
+    let statement = match directive {
+        "byte" => {
+            let mut bytes = Vec::new();
+            for value in cursor.list(|cursor| cursor.constant(constants))? {
+                let byte = u8::try_from(value)
+                    .or_else(|_| i8::try_from(value).map(|value| value as u8))
+                    .map_err(|_| format!("{value} is not a byte"))?;
+                bytes.push(byte);
+            }
+            Statement::Data(bytes)
+        }
+        "word" => Statement::Words(cursor.list(|cursor| cursor.expr(constants))?),
+        "string" | "asciz" => {
+            let mut bytes = cursor.quoted('"')?;
+            bytes.push(0);
+            Statement::Data(bytes)
+        }
+        "space" => {
+            let size = cursor.constant(constants)?;
+            let size = usize::try_from(size).map_err(|_| format!("invalid size {size}"))?;
+            Statement::Space(size)
+        }
+        _ => return Err(format!("unknown directive `.{directive}`")),
+    };
+    cursor.end()?;
+    Ok(statement)
+}
+
+/// Expansion of `li rd, value`: values which do not fit in the 16-bit
+/// immediate of `loadimm` are built from two halves, and addresses of labels
+/// are loaded by a single `loadimm`.
+fn load_constant(rd: u8, imm: Imm) -> Result<Statement, String> {
+    let value = imm.value;
+    if !imm.labels.is_empty() {
+        return Ok(Statement::LoadImm(rd, imm));
+    }
+    if let Ok(imm) = i16::try_from(value) {
+        return Ok(Statement::Instruction(Instruction::LoadImm { rd, imm }));
+    }
+    if !(i64::from(i32::MIN)..=i64::from(u32::MAX)).contains(&value) {
+        return Err(format!("{value} does not fit in 32 bits"));
+    }
+    // The low half is sign-extended, which the high half compensates for
+    let low = value as i16;
+    let high = ((value - i64::from(low)) >> 16) as i16;
+    let scratch = if rd == 3 { 4 } else { 3 };
+    let mut instructions = vec![
+        Instruction::LoadImm { rd, imm: high },
+        Instruction::LoadImm {
+            rd: scratch,
+            imm: 16,
+        },
+        Instruction::Binary {
+            op: BinaryOp::Shl,
+            rd,
+            rs1: rd,
+            rs2: scratch,
+        },
+    ];
+    if low != 0 {
+        instructions.push(Instruction::LoadImm {
+            rd: scratch,
+            imm: low,
+        });
+        instructions.push(Instruction::Binary {
+            op: BinaryOp::Add,
+            rd,
+            rs1: rd,
+            rs2: scratch,
+        });
+    }
+    Ok(Statement::Pseudo(instructions))
+}
+
+/// Minimal tokenizer working directly on the text of a line.
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(line: &'a str) -> Self {
+        Cursor { rest: line }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest.chars().next()
+    }
+
+    /// Check if nothing but a comment remains on the line.
+    fn at_end(&mut self) -> bool {
+        matches!(self.peek(), None | Some(';'))
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(format!("unexpected `{}`", self.rest.trim_end()))
+        }
+    }
+
+    /// Skip the address column of a listing (`0012` or `????`).
+    fn skip_address(&mut self) {
+        self.skip_whitespace();
+        let word = self.rest.split_whitespace().next().unwrap_or("");
+        if !word.is_empty()
+            && (word.bytes().all(|b| b.is_ascii_digit()) || word.bytes().all(|b| b == b'?'))
+        {
+            self.rest = &self.rest[word.len()..];
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected `{token}`"))
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), String> {
+        let saved = self.rest;
+        if self.ident() == Some(word) {
+            Ok(())
+        } else {
+            self.rest = saved;
+            Err(format!("expected `{word}`"))
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest.len());
+        if len == 0 || self.rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let (ident, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Some(ident)
+    }
+
+    /// Parse a `name:` label definition, leaving the cursor untouched if
+    /// there is none.
+    fn label(&mut self) -> Option<&'a str> {
+        let saved = self.rest;
+        if let Some(name) = self.ident()
+            && let Some(rest) = self.rest.strip_prefix(':')
+        {
+            self.rest = rest;
+            return Some(name);
+        }
+        self.rest = saved;
+        None
+    }
+
+    fn reg(&mut self) -> Result<u8, String> {
+        let saved = self.rest;
+        let reg = self
+            .ident()
+            .and_then(|ident| ident.strip_prefix('r'))
+            .and_then(|number| number.parse::<u8>().ok())
+            .filter(|&reg| reg < 16);
+        reg.ok_or_else(|| {
+            let found = saved.split_whitespace().next().unwrap_or("end of line");
+            format!("expected a register (r0 to r15), found `{found}`")
+        })
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        self.skip_whitespace();
+        let negative = self.eat("-");
+        let (radix, digits) = match self.rest.strip_prefix("0x") {
+            Some(rest) => (16, rest),
+            None => (10, self.rest),
+        };
+        let len = digits
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(digits.len());
+        let value = i64::from_str_radix(&digits[..len], radix)
+            .map_err(|_| format!("invalid number `{}`", &digits[..len]))?;
+        self.rest = &digits[len..];
+        Ok(if negative { -value } else { value })
+    }
+
+    fn imm(&mut self, constants: &Constants) -> Result<Imm, String> {
+        self.expect("#")?;
+        self.expr(constants)
+    }
+
+    /// Parse numbers, constants and labels added or subtracted, such as
+    /// `str_1+4`.
+    fn expr(&mut self, constants: &Constants) -> Result<Imm, String> {
+        let mut imm = Imm::default();
+        let mut sign = if self.eat("-") { -1 } else { 1 };
+        loop {
+            let value = match self.ident() {
+                Some(name) => match constants.get(name) {
+                    Some(&value) => value,
+                    None => {
+                        imm.labels.push((sign, name.to_owned()));
+                        0
+                    }
+                },
+                None => self.number()?,
+            };
+            imm.value = (value.checked_mul(sign))
+                .and_then(|value| imm.value.checked_add(value))
+                .ok_or_else(|| "expression overflows".to_owned())?;
+            sign = if self.eat("+") {
+                1
+            } else if self.eat("-") {
+                -1
+            } else {
+                return Ok(imm);
+            };
+        }
+    }
+
+    /// Parse an expression made of numbers and constants only.
+    fn constant(&mut self, constants: &Constants) -> Result<i64, String> {
+        let imm = self.expr(constants)?;
+        match imm.labels.first() {
+            Some((_, label)) => Err(format!("`{label}` is not a constant")),
+            None => Ok(imm.value),
+        }
+    }
+
+    /// Parse items separated by commas.
+    fn list<T>(&mut self, item: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut items = vec![item(self)?];
+        while self.eat(",") {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    /// Parse a double-quoted string, without escapes.
+    fn string(&mut self) -> Result<&'a str, String> {
+        self.expect("\"")?;
+        let end = (self.rest.find('"')).ok_or_else(|| "unterminated string".to_owned())?;
+        let string = &self.rest[..end];
+        self.rest = &self.rest[end + 1..];
+        Ok(string)
+    }
+
+    /// Parse the name and parameters of a `.macro` directive.
+    fn macro_signature(&mut self) -> Result<(String, Vec<String>), String> {
+        let name = self.ident().ok_or("expected a macro name")?;
+        let mut params = Vec::new();
+        while !self.at_end() {
+            if !params.is_empty() {
+                self.expect(",")?;
+            }
+            let param = self.ident().ok_or("expected a parameter name")?;
+            params.push(param.to_owned());
+        }
+        Ok((name.to_owned(), params))
+    }
+
+    /// Parse the name of a macro, leaving the cursor untouched if there is
+    /// none.
+    fn macro_name(&mut self, macros: &BTreeMap<String, Macro>) -> Option<&'a str> {
+        let saved = self.rest;
+        let name = self.ident().filter(|name| macros.contains_key(*name));
+        if name.is_none() {
+            self.rest = saved;
+        }
+        name
+    }
+
+    /// Arguments of a macro, separated by commas, up to the end of the
+    /// line or to a comment.
+    fn macro_args(&mut self) -> Vec<&'a str> {
+        let (args, _) = self.rest.split_once(';').unwrap_or((self.rest, ""));
+        self.rest = "";
+        if args.trim().is_empty() {
+            return Vec::new();
+        }
+        args.split(',').map(str::trim).collect()
+    }
+
+    /// Parse a list of bytes such as `[0, 0, 0, 0]`.
+    fn byte_list(&mut self) -> Result<Vec<u8>, String> {
+        self.expect("[")?;
+        let mut bytes = Vec::new();
+        if self.eat("]") {
+            return Ok(bytes);
+        }
+        loop {
+            let value = self.number()?;
+            bytes.push(u8::try_from(value).map_err(|_| format!("{value} is not a byte"))?);
+            if self.eat("]") {
+                return Ok(bytes);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// Parse a byte string such as `b'Hello\n'` if one is present.
+    fn byte_string(&mut self) -> Result<Option<Vec<u8>>, String> {
+        self.skip_whitespace();
+        let mut chars = self.rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some('b'), Some(quote @ ('\'' | '"'))) => {
+                self.rest = &self.rest[1..];
+                self.quoted(quote).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Parse a string delimited by `quote`, with escapes such as `\n` or
+    /// `\x41`.
+    fn quoted(&mut self, quote: char) -> Result<Vec<u8>, String> {
+        self.expect(&quote.to_string())?;
+        let mut chars = self.rest.char_indices();
+        let mut bytes = Vec::new();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let escaped = match chars.next() {
+                        Some((_, 'n')) => b'\n',
+                        Some((_, 'r')) => b'\r',
+                        Some((_, 't')) => b'\t',
+                        Some((_, '0')) => 0,
+                        Some((_, c @ ('\\' | '\'' | '"'))) => c as u8,
+                        Some((_, 'x')) => {
+                            let hex: String = chars.by_ref().take(2).map(|(_, c)| c).collect();
+                            u8::from_str_radix(&hex, 16)
+                                .map_err(|_| format!("invalid escape `\\x{hex}`"))?
+                        }
+                        Some((_, c)) => return Err(format!("invalid escape `\\{c}`")),
+                        None => break,
+                    };
+                    bytes.push(escaped);
+                }
+                c if c == quote => {
+                    self.rest = &self.rest[index + 1..];
+                    return Ok(bytes);
+                }
+                c => {
+                    let mut buffer = [0; 4];
+                    bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+                }
+            }
+        }
+        Err("unterminated string".to_owned())
+    }
+}
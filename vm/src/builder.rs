@@ -0,0 +1,113 @@
+use crate::machine::{IP, NREGS};
+use crate::{Error, MEMORY_SIZE, Machine, Object};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Configuration of a new [`Machine`], obtained through
+/// [`Machine::builder`].
+#[derive(Clone, Debug)]
+pub struct MachineBuilder {
+    memory_size: usize,
+    load_address: usize,
+    registers: Vec<(usize, u32)>,
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        MachineBuilder {
+            memory_size: MEMORY_SIZE,
+            load_address: 0,
+            registers: Vec::new(),
+        }
+    }
+}
+
+impl MachineBuilder {
+    /// Create a builder for a machine with `MEMORY_SIZE` bytes of memory,
+    /// loading its image at address 0, and with all registers cleared.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the size in bytes of the machine memory.
+    #[must_use]
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    /// Set the address where the image is copied. Unless the IP is set
+    /// explicitly with [`register`](MachineBuilder::register), the
+    /// execution starts at this address.
+    #[must_use]
+    pub fn load_address(mut self, address: usize) -> Self {
+        self.load_address = address;
+        self
+    }
+
+    /// Set the initial value of a register.
+    #[must_use]
+    pub fn register(mut self, reg: usize, value: u32) -> Self {
+        self.registers.push((reg, value));
+        self
+    }
+
+    /// Create the machine with `image` copied at the load address.
+    ///
+    /// # Errors
+    /// This function returns an error when the image does not fit in memory,
+    /// or when an initial value is given to a register above 15.
+    pub fn build(&self, image: &[u8]) -> Result<Machine> {
+        self.build_at(image, self.load_address, self.load_address)
+    }
+
+    /// Create the machine from the content of a program file, either an
+    /// [`Object`] whose sections are copied at their address and whose
+    /// execution starts at its entry point (the load address is ignored),
+    /// or a raw image handled like [`build`](MachineBuilder::build) does.
+    ///
+    /// # Errors
+    /// This function returns an error when the object file is invalid or
+    /// refers to undefined symbols (it must be linked first), and in the
+    /// same cases as [`build`](MachineBuilder::build).
+    pub fn load(&self, file: &[u8]) -> Result<Machine> {
+        if Object::is_object(file) {
+            let object = Object::decode(file)?;
+            if let Some(symbol) = object.undefined().first() {
+                return Err(Error::UndefinedSymbol((*symbol).to_owned()));
+            }
+            // Sections may lie far away, check before building the image
+            if object.size() > self.memory_size {
+                return Err(Error::ProgramTooLarge {
+                    size: object.size(),
+                    memory_size: self.memory_size,
+                });
+            }
+            self.build_at(&object.image(), 0, object.entry)
+        } else {
+            self.build(file)
+        }
+    }
+
+    fn build_at(&self, image: &[u8], load_address: usize, entry: usize) -> Result<Machine> {
+        let end = load_address
+            .checked_add(image.len())
+            .filter(|&end| end <= self.memory_size)
+            .ok_or(Error::ProgramTooLarge {
+                size: load_address.saturating_add(image.len()),
+                memory_size: self.memory_size,
+            })?;
+        let mut memory = vec![0; self.memory_size];
+        memory[load_address..end].copy_from_slice(image);
+
+        let mut registers = [0; NREGS];
+        registers[IP] = entry as u32;
+        for &(reg, value) in &self.registers {
+            *registers
+                .get_mut(reg)
+                .ok_or(Error::RegistreOverdepass { reg })? = value;
+        }
+        Ok(Machine::from_parts(memory, registers))
+    }
+}
@@ -0,0 +1,269 @@
+use crate::machine::{IP, NREGS, SP};
+use crate::{Error, Instruction, Object};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Problem found by [`check`] in a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Reachable instruction with an unknown opcode
+    InvalidOpcode { address: usize, opcode: u8 },
+    /// Reachable instruction using register `reg`, which does not exist
+    InvalidRegister { address: usize, reg: usize },
+    /// Reachable instruction whose encoding crosses the end of memory
+    CrossesMemoryEnd { address: usize },
+    /// Jump from `address` to a `target` outside of memory
+    InvalidTarget { address: usize, target: usize },
+    /// Entry point outside of memory
+    EntryOutsideMemory { entry: usize },
+    /// Bytes of the program from `start` to `end` (excluded) which are not
+    /// part of any reachable instruction, such as data
+    Unreachable { start: usize, end: usize },
+}
+
+impl Problem {
+    /// Address where the problem is located.
+    #[must_use]
+    pub fn address(&self) -> usize {
+        match *self {
+            Problem::InvalidOpcode { address, .. }
+            | Problem::InvalidRegister { address, .. }
+            | Problem::CrossesMemoryEnd { address }
+            | Problem::InvalidTarget { address, .. }
+            | Problem::EntryOutsideMemory { entry: address }
+            | Problem::Unreachable { start: address, .. } => address,
+        }
+    }
+
+    /// Whether the program fails if it runs into this problem, as opposed
+    /// to unreachable bytes which are merely suspicious.
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Problem::Unreachable { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::InvalidOpcode { address, opcode } => {
+                write!(f, "at address {address:04}: invalid opcode {opcode}")
+            }
+            Problem::InvalidRegister { address, reg } => {
+                write!(f, "at address {address:04}: register r{reg} does not exist")
+            }
+            Problem::CrossesMemoryEnd { address } => write!(
+                f,
+                "at address {address:04}: instruction crosses the end of memory"
+            ),
+            Problem::InvalidTarget { address, target } => write!(
+                f,
+                "at address {address:04}: jump to address {target} is out of bounds"
+            ),
+            Problem::EntryOutsideMemory { entry } => {
+                write!(f, "entry point {entry} is outside of memory")
+            }
+            Problem::Unreachable { start, end } => {
+                write!(f, "at addresses {start:04}-{:04}: unreachable", end - 1)
+            }
+        }
+    }
+}
+
+// What is known of the registers along a path of the control flow
+#[derive(Clone, Copy)]
+struct State {
+    constants: [Option<u32>; NREGS],
+    // last constant stored in memory, as a possible return address
+    stored: Option<u32>,
+}
+
+/// Check a program before running it on a machine with `memory_size` bytes
+/// of memory, returning the problems found sorted by address.
+///
+/// Instructions are decoded by following the control flow from address 0.
+/// Jumps are followed when their target is known: `call` and branches, as
+/// well as constants loaded with `loadimm` which reach r0, directly or
+/// through other instructions. A jump to a constant target is assumed to be
+/// a call returning just after it when this address was stored in memory
+/// (as the compiled programs do). Indirect jumps, such as returns, end the
+/// path.
+#[must_use]
+pub fn check(program: &[u8], memory_size: usize) -> Vec<Problem> {
+    check_from(program, 0, memory_size)
+}
+
+/// Similar to [`check`], but for an object file, following the control flow
+/// from its entry point. Only the bytes of its sections may be reported as
+/// unreachable. The sections are expected to fit in `memory_size` bytes,
+/// see [`Object::size`].
+#[must_use]
+pub fn check_object(object: &Object, memory_size: usize) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for problem in check_from(&object.image(), object.entry, memory_size) {
+        let Problem::Unreachable { start, end } = problem else {
+            problems.push(problem);
+            continue;
+        };
+        for section in &object.sections {
+            let (start, end) = (start.max(section.address), end.min(section.end()));
+            if start < end {
+                problems.push(Problem::Unreachable { start, end });
+            }
+        }
+    }
+    problems.sort_by_key(Problem::address);
+    problems
+}
+
+fn check_from(program: &[u8], entry: usize, memory_size: usize) -> Vec<Problem> {
+    let mut memory = program.to_vec();
+    memory.resize(memory_size.max(program.len()), 0);
+    let mut problems = Vec::new();
+    let mut covered = vec![false; program.len()];
+    let mut visited = BTreeSet::new();
+    let initial = State {
+        constants: [None; NREGS],
+        stored: None,
+    };
+    let mut pending = Vec::new();
+    if entry < memory_size {
+        pending.push((entry, initial));
+    } else {
+        problems.push(Problem::EntryOutsideMemory { entry });
+    }
+    while let Some((address, mut state)) = pending.pop() {
+        if !visited.insert(address) {
+            continue;
+        }
+        let (instruction, size) = match Instruction::decode(&memory[address..memory_size]) {
+            Ok(decoded) => decoded,
+            Err(error) => {
+                // The faulty instruction is not reported as unreachable too
+                let opcode = memory.get(address).copied();
+                let size = opcode.and_then(Instruction::size_of).unwrap_or(1);
+                for byte in covered.iter_mut().skip(address).take(size) {
+                    *byte = true;
+                }
+                problems.push(match error {
+                    Error::InstructionError { opcode } => {
+                        Problem::InvalidOpcode { address, opcode }
+                    }
+                    Error::RegistreOverdepass { reg } => Problem::InvalidRegister { address, reg },
+                    _ => Problem::CrossesMemoryEnd { address },
+                });
+                continue;
+            }
+        };
+        for byte in covered.iter_mut().skip(address).take(size) {
+            *byte = true;
+        }
+        let next = address + size;
+        let mut targets = Vec::new();
+        match instruction {
+            Instruction::Exit
+            | Instruction::Ret
+            | Instruction::Iret
+            | Instruction::Load { rd: 0, .. }
+            | Instruction::Pop { rd: 0 }
+            | Instruction::In { rd: 0 }
+            | Instruction::InNumber { rd: 0 } => (),
+            Instruction::Call { target } => {
+                state.constants[SP] = None;
+                state.stored = Some(next as u32);
+                targets.extend([target as u32, next as u32]);
+            }
+            Instruction::Branch { offset, .. } => {
+                targets.push(next as u32);
+                targets.push((address as i64 + i64::from(offset)) as u32);
+            }
+            Instruction::MoveIf { rd: 0, rs, .. } => {
+                targets.push(next as u32);
+                targets.extend(state.constants[rs as usize]);
+            }
+            _ => {
+                update(&mut state, instruction);
+                match state.constants[IP] {
+                    None if writes_ip(instruction) => (),
+                    None => targets.push(next as u32),
+                    Some(target) => {
+                        targets.push(target);
+                        if state.stored == Some(next as u32) {
+                            targets.push(next as u32);
+                        }
+                    }
+                }
+            }
+        }
+        // The IP is not a constant once the jump is done
+        state.constants[IP] = None;
+        for target in targets {
+            let target = target as usize;
+            if target < memory_size {
+                pending.push((target, state));
+            } else {
+                problems.push(Problem::InvalidTarget { address, target });
+            }
+        }
+    }
+
+    let mut start = None;
+    for (address, &covered) in covered.iter().chain([&true]).enumerate() {
+        match (start, covered) {
+            (None, false) => start = Some(address),
+            (Some(first), true) => {
+                problems.push(Problem::Unreachable {
+                    start: first,
+                    end: address,
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    problems.sort_by_key(Problem::address);
+    problems
+}
+
+// Whether the instruction computes r0, jumping to wherever the result is
+fn writes_ip(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Sub { rd: 0, .. }
+            | Instruction::Binary { rd: 0, .. }
+            | Instruction::Not { rd: 0, .. }
+    )
+}
+
+// Follow the constants through an instruction which does not jump by itself
+fn update(state: &mut State, instruction: Instruction) {
+    let constants = &mut state.constants;
+    match instruction {
+        Instruction::LoadImm { rd, imm } => constants[rd as usize] = Some(imm as i32 as u32),
+        Instruction::Sub { rd, rs1, rs2 } => {
+            constants[rd as usize] = constants[rs1 as usize]
+                .zip(constants[rs2 as usize])
+                .map(|(a, b)| a.wrapping_sub(b));
+        }
+        Instruction::Binary { op, rd, rs1, rs2 } => {
+            constants[rd as usize] = constants[rs1 as usize]
+                .zip(constants[rs2 as usize])
+                .and_then(|(a, b)| op.apply(a, b));
+        }
+        Instruction::Not { rd, rs } => constants[rd as usize] = constants[rs as usize].map(|a| !a),
+        Instruction::Store { rs, .. } => state.stored = constants[rs as usize],
+        Instruction::Push { rs } => {
+            state.stored = constants[rs as usize];
+            constants[SP] = None;
+        }
+        Instruction::Pop { rd } => {
+            constants[rd as usize] = None;
+            constants[SP] = None;
+        }
+        Instruction::MoveIf { rd, .. }
+        | Instruction::Load { rd, .. }
+        | Instruction::In { rd }
+        | Instruction::InNumber { rd } => constants[rd as usize] = None,
+        _ => (),
+    }
+}
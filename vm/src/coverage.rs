@@ -0,0 +1,113 @@
+use crate::{AsmError, Assembler, Profile};
+use std::fmt::Write as _;
+
+/// Instructions of a listing which were executed, gathered from the
+/// [`Profile`] of a run.
+pub struct Coverage {
+    listing: String,
+    // line (starting at 1) and execution count of each instruction
+    hits: Vec<(usize, u64)>,
+}
+
+impl Coverage {
+    /// Count the executions of the instructions of `listing`, in the format
+    /// of the `.dis` files, recorded in `profile`.
+    ///
+    /// # Errors
+    /// An error is returned if `listing` cannot be assembled.
+    pub fn new(listing: &str, profile: &Profile) -> Result<Self, AsmError> {
+        Coverage::with_assembler(listing, &Assembler::new(), profile)
+    }
+
+    /// Similar to [`new`](Coverage::new), but assemble `listing` with
+    /// `assembler`, which knows where its includes are.
+    ///
+    /// # Errors
+    /// An error is returned if `listing` cannot be assembled.
+    pub fn with_assembler(
+        listing: &str,
+        assembler: &Assembler,
+        profile: &Profile,
+    ) -> Result<Self, AsmError> {
+        let mut hits: Vec<(usize, u64)> = Vec::new();
+        for (line, address) in assembler.instruction_lines(listing)? {
+            let count = profile.count(address);
+            // Lines expanding to several instructions count once
+            match hits.last_mut() {
+                Some((last, hit)) if *last == line => *hit = (*hit).max(count),
+                _ => hits.push((line, count)),
+            }
+        }
+        Ok(Coverage {
+            listing: listing.to_owned(),
+            hits,
+        })
+    }
+
+    /// Number of instructions in the listing, macro invocations and
+    /// pseudo-instructions counting as one.
+    #[must_use]
+    pub fn instructions(&self) -> usize {
+        self.hits.len()
+    }
+
+    /// Number of instructions executed at least once.
+    #[must_use]
+    pub fn covered(&self) -> usize {
+        self.hits.iter().filter(|&&(_, count)| count > 0).count()
+    }
+
+    /// Percentage of the instructions executed at least once.
+    #[must_use]
+    pub fn percent(&self) -> f64 {
+        100.0 * self.covered() as f64 / self.instructions().max(1) as f64
+    }
+
+    /// Line numbers of the instructions never executed.
+    #[must_use]
+    pub fn missed(&self) -> Vec<usize> {
+        (self.hits.iter())
+            .filter(|&&(_, count)| count == 0)
+            .map(|&(line, _)| line)
+            .collect()
+    }
+
+    /// The listing with the execution count of each instruction in front of
+    /// it, or `#####` if it was never executed, followed by a summary.
+    #[must_use]
+    pub fn annotate(&self) -> String {
+        let mut hits = self.hits.iter().peekable();
+        let mut text = String::new();
+        for (index, line) in self.listing.lines().enumerate() {
+            match hits.next_if(|&&(hit_line, _)| hit_line == index + 1) {
+                Some((_, 0)) => writeln!(text, "{:>9} | {line}", "#####"),
+                Some((_, count)) => writeln!(text, "{count:>9} | {line}"),
+                None => writeln!(text, "{:>9} | {line}", ""),
+            }
+            .unwrap();
+        }
+        writeln!(
+            text,
+            "coverage: {}/{} instructions ({:.2}%)",
+            self.covered(),
+            self.instructions(),
+            self.percent()
+        )
+        .unwrap();
+        text
+    }
+
+    /// Coverage in the lcov tracefile format, for the listing found at
+    /// `path`.
+    #[must_use]
+    pub fn lcov(&self, path: &str) -> String {
+        let mut text = format!("TN:\nSF:{path}\n");
+        for (line, count) in &self.hits {
+            writeln!(text, "DA:{line},{count}").unwrap();
+        }
+        writeln!(text, "LF:{}", self.instructions()).unwrap();
+        writeln!(text, "LH:{}", self.covered()).unwrap();
+        text += "end_of_record\n";
+        text
+    }
+}
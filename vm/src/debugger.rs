@@ -0,0 +1,231 @@
+use crate::{Instruction, Labels, Machine};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+break <addr|label>        stop before executing the instruction at this address
+delete <addr|label>       remove a breakpoint
+step [count]              execute one or several instructions
+continue                  run until a breakpoint or the end of the program
+back [count]              undo one or several instructions
+regs                      show the registers
+mem <addr|label|reg> [n]  dump n bytes of memory (64 by default) around an address
+set r<n> <value>          change the value of a register
+quit                      leave the debugger";
+
+/// Interactive debugger controlling a [`Machine`] through text commands.
+pub struct Debugger {
+    machine: Machine,
+    labels: Labels,
+    breakpoints: BTreeSet<usize>,
+    finished: bool,
+}
+
+impl Debugger {
+    /// Create a debugger for `machine`. The `labels` (which may be empty)
+    /// can be used in place of addresses in commands.
+    #[must_use]
+    pub fn new(machine: Machine, labels: Labels) -> Self {
+        Debugger {
+            machine,
+            labels,
+            breakpoints: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    /// Reference onto the debugged machine.
+    #[must_use]
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Read commands from `input` until it is exhausted or a `quit` command
+    /// is found. Both the debugger messages and the program output are
+    /// printed on `output`.
+    ///
+    /// # Errors
+    /// This function returns an error if `input` or `output` fail.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        self.show_next(output)?;
+        write!(output, "(vm) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => (),
+                ["quit" | "q"] => return Ok(()),
+                ["help" | "h"] => writeln!(output, "{HELP}")?,
+                ["break" | "b", location] => match self.address(location) {
+                    Some(address) => {
+                        self.breakpoints.insert(address);
+                        writeln!(output, "breakpoint at {}", self.describe(address))?;
+                    }
+                    None => writeln!(output, "unknown location `{location}`")?,
+                },
+                ["delete" | "d", location] => match self.address(location) {
+                    Some(address) if self.breakpoints.remove(&address) => {
+                        writeln!(output, "deleted breakpoint at {}", self.describe(address))?;
+                    }
+                    _ => writeln!(output, "no breakpoint at `{location}`")?,
+                },
+                ["step" | "s"] => self.step(1, output)?,
+                ["step" | "s", count] => match count.parse() {
+                    Ok(count) => self.step(count, output)?,
+                    Err(_) => writeln!(output, "invalid count `{count}`")?,
+                },
+                ["continue" | "c"] => self.cont(output)?,
+                ["back"] => self.back(1, output)?,
+                ["back", count] => match count.parse() {
+                    Ok(count) => self.back(count, output)?,
+                    Err(_) => writeln!(output, "invalid count `{count}`")?,
+                },
+                ["regs" | "r"] => self.show_regs(output)?,
+                ["mem" | "x", location] => self.dump(location, "64", output)?,
+                ["mem" | "x", location, len] => self.dump(location, len, output)?,
+                ["set", reg, value] => self.set(reg, value, output)?,
+                _ => writeln!(output, "unknown command `{line}`, try `help`")?,
+            }
+            write!(output, "(vm) ")?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    /// Resolve a decimal (or `0x` hexadecimal) address, a label, or a
+    /// register holding the address.
+    fn address(&self, location: &str) -> Option<usize> {
+        if let Some(reg) = location.strip_prefix('r')
+            && let Ok(reg) = reg.parse()
+        {
+            return self.machine.get_reg(reg).ok().map(|value| value as usize);
+        }
+        match location.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => location
+                .parse()
+                .ok()
+                .or_else(|| self.labels.get(location).copied()),
+        }
+    }
+
+    /// Render an address along with the label found there, if any.
+    fn describe(&self, address: usize) -> String {
+        match self.labels.iter().find(|(_, a)| **a == address) {
+            Some((label, _)) => format!("{address:04} <{label}>"),
+            None => format!("{address:04}"),
+        }
+    }
+
+    fn show_next<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        if self.finished {
+            return writeln!(output, "the program is not running");
+        }
+        let ip = self.machine.regs()[0] as usize;
+        match self.machine.memory().get(ip..).map(Instruction::decode) {
+            Some(Ok((instruction, _))) => {
+                writeln!(output, "{}   {instruction}", self.describe(ip))
+            }
+            _ => writeln!(output, "{}   (invalid instruction)", self.describe(ip)),
+        }
+    }
+
+    /// Execute one instruction, returning `false` if the program stopped.
+    fn execute<W: Write>(&mut self, output: &mut W) -> io::Result<bool> {
+        match self.machine.step_on(output) {
+            Ok(false) => return Ok(true),
+            Ok(true) => writeln!(output, "program exited")?,
+            Err(e) => writeln!(output, "program stopped on error: {e}")?,
+        }
+        self.finished = true;
+        Ok(false)
+    }
+
+    fn step<W: Write>(&mut self, count: usize, output: &mut W) -> io::Result<()> {
+        for _ in 0..count {
+            if self.finished || !self.execute(output)? {
+                break;
+            }
+        }
+        self.show_next(output)
+    }
+
+    fn cont<W: Write>(&mut self, output: &mut W) -> io::Result<()> {
+        while !self.finished && self.execute(output)? {
+            let ip = self.machine.regs()[0] as usize;
+            if self.breakpoints.contains(&ip) {
+                writeln!(output, "breakpoint reached")?;
+                break;
+            }
+        }
+        self.show_next(output)
+    }
+
+    fn back<W: Write>(&mut self, count: usize, output: &mut W) -> io::Result<()> {
+        for undone in 0..count {
+            if !self.machine.step_back() {
+                writeln!(
+                    output,
+                    "no more history after undoing {undone} instructions"
+                )?;
+                break;
+            }
+            self.finished = false;
+        }
+        self.show_next(output)
+    }
+
+    fn show_regs<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (reg, value) in self.machine.regs().iter().enumerate() {
+            write!(output, "r{reg:<2} = 0x{value:08x} {:>11}", *value as i32)?;
+            if reg % 2 == 1 {
+                writeln!(output)?;
+            } else {
+                write!(output, "    ")?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dump<W: Write>(&self, location: &str, len: &str, output: &mut W) -> io::Result<()> {
+        let (Some(address), Ok(len)) = (self.address(location), len.parse::<usize>()) else {
+            return writeln!(output, "invalid memory range `{location} {len}`");
+        };
+        // Start a few lines before the address to show its surroundings
+        let memory = self.machine.memory();
+        let start = (address & !15)
+            .saturating_sub((len / 2) & !15)
+            .min(memory.len());
+        let end = (start + len).min(memory.len());
+        for (row, bytes) in memory[start..end].chunks(16).enumerate() {
+            write!(output, "{:04x}:", start + 16 * row)?;
+            for byte in bytes {
+                write!(output, " {byte:02x}")?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    fn set<W: Write>(&mut self, reg: &str, value: &str, output: &mut W) -> io::Result<()> {
+        let reg = reg.strip_prefix('r').and_then(|reg| reg.parse().ok());
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => value
+                .parse::<u32>()
+                .ok()
+                .or_else(|| value.parse::<i32>().ok().map(|v| v as u32)),
+        };
+        match (reg, value) {
+            (Some(reg), Some(value)) if self.machine.set_reg(reg, value).is_ok() => {
+                if reg == 0 {
+                    self.finished = false;
+                    self.show_next(output)?;
+                }
+                Ok(())
+            }
+            _ => writeln!(output, "invalid register or value"),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::Error;
+use crate::snapshot::invalid;
+use std::io::{self, Read, Write};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Peripheral mapped onto a range of addresses with
+/// [`Machine::map_device`](crate::Machine::map_device). The `load` and
+/// `store` instructions reaching this range call into the device instead of
+/// accessing memory, with the offset of the accessed word from the start of
+/// the range.
+pub trait Device {
+    /// Number of bytes of the address range taken by the device.
+    fn size(&self) -> usize;
+
+    /// Read the 32-bit word at `offset`.
+    ///
+    /// # Errors
+    /// The default implementation refuses every read with
+    /// [`Error::UnsupportedAccess`].
+    fn load(&mut self, offset: usize) -> Result<u32> {
+        let _ = offset;
+        Err(Error::UnsupportedAccess)
+    }
+
+    /// Write the 32-bit word `value` at `offset`.
+    ///
+    /// # Errors
+    /// The default implementation refuses every write with
+    /// [`Error::UnsupportedAccess`].
+    fn store(&mut self, offset: usize, value: u32) -> Result<()> {
+        let _ = (offset, value);
+        Err(Error::UnsupportedAccess)
+    }
+
+    /// Called once after every executed instruction.
+    fn tick(&mut self) {}
+
+    /// State of the device, to be put in a snapshot of the machine.
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restore the state produced by [`save`](Device::save).
+    ///
+    /// # Errors
+    /// This function returns an error if `state` is not a valid state of the
+    /// device. The default implementation only accepts an empty state.
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        if state.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("unexpected device state"))
+        }
+    }
+}
+
+/// Decode the state of a device made of a single integer.
+fn restore_word<const N: usize>(state: &[u8]) -> Result<[u8; N]> {
+    state
+        .try_into()
+        .map_err(|_| invalid("invalid device state"))
+}
+
+/// Character console taking one word: storing prints the low byte of the
+/// value, and loading reads one byte, or -1 at the end of input.
+pub struct Console {
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+}
+
+impl Console {
+    /// Create a console reading from `input` and printing on `output`.
+    pub fn new(input: impl Read + 'static, output: impl Write + 'static) -> Self {
+        Console {
+            input: Box::new(input),
+            output: Box::new(output),
+        }
+    }
+
+    /// Create a console using the standard input and output.
+    #[must_use]
+    pub fn stdio() -> Self {
+        Console::new(io::stdin(), io::stdout())
+    }
+}
+
+impl Device for Console {
+    fn size(&self) -> usize {
+        4
+    }
+
+    fn load(&mut self, _offset: usize) -> Result<u32> {
+        let mut byte = 0;
+        loop {
+            match self.input.read(std::slice::from_mut(&mut byte)) {
+                Ok(0) => return Ok(u32::MAX),
+                Ok(_) => return Ok(u32::from(byte)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(Error::InputError(e)),
+            }
+        }
+    }
+
+    fn store(&mut self, _offset: usize, value: u32) -> Result<()> {
+        self.output
+            .write_all(&[value as u8])
+            .and_then(|()| self.output.flush())
+            .map_err(Error::OutputError)
+    }
+}
+
+/// Read-only counter of the instructions executed since the device was
+/// mapped, as a 64-bit number whose low word comes first.
+#[derive(Clone, Debug, Default)]
+pub struct TickCounter {
+    ticks: u64,
+}
+
+impl TickCounter {
+    /// Create a counter starting at 0.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for TickCounter {
+    fn size(&self) -> usize {
+        8
+    }
+
+    fn load(&mut self, offset: usize) -> Result<u32> {
+        match offset {
+            0 => Ok(self.ticks as u32),
+            4 => Ok((self.ticks >> 32) as u32),
+            _ => Err(Error::UnsupportedAccess),
+        }
+    }
+
+    fn tick(&mut self) {
+        self.ticks += 1;
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.ticks.to_le_bytes().to_vec()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        self.ticks = u64::from_le_bytes(restore_word(state)?);
+        Ok(())
+    }
+}
+
+/// Pseudo-random number generator taking one word: loading gives the next
+/// number, and storing seeds the generator again. The same seed always
+/// gives the same sequence.
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: u32,
+}
+
+impl Random {
+    /// Create a generator from `seed`.
+    #[must_use]
+    pub fn new(seed: u32) -> Self {
+        let mut random = Random { state: 0 };
+        random.seed(seed);
+        random
+    }
+
+    fn seed(&mut self, seed: u32) {
+        // xorshift gets stuck on 0
+        self.state = if seed == 0 { 0x9e37_79b9 } else { seed };
+    }
+}
+
+impl Device for Random {
+    fn size(&self) -> usize {
+        4
+    }
+
+    fn load(&mut self, _offset: usize) -> Result<u32> {
+        // xorshift32
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        Ok(x)
+    }
+
+    fn store(&mut self, _offset: usize, value: u32) -> Result<()> {
+        self.seed(value);
+        Ok(())
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        self.state = u32::from_le_bytes(restore_word(state)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{Instruction, Object, Section, SectionKind};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Disassemble a memory image into a listing in the format of the `.dis`
+/// files, which [`assemble`](crate::assemble) turns back into the same image.
+///
+/// Instructions are decoded from address 0 until the first byte sequence
+/// which is not a valid instruction; the remaining bytes are rendered as
+/// data, as a byte string or as a list of bytes for zeroed areas. Labels are
+/// synthesized for jump targets (`call` and branch targets, and constants which reach
+/// r0 directly, through a `move`, or through memory as a pushed return
+/// address) and for data referenced by a `loadimm`.
+#[must_use]
+pub fn disassemble(memory: &[u8]) -> String {
+    let (code, size) = decode(memory, 0);
+    render(&[code], &[(size, &memory[size..])])
+}
+
+/// Similar to [`disassemble`], but for an object file: each code section
+/// is decoded from its own address, while data sections are rendered as
+/// data. A `.org` directive precedes sections which do not follow the
+/// previous one.
+#[must_use]
+pub fn disassemble_object(object: &Object) -> String {
+    let mut sections: Vec<&Section> = object.sections.iter().collect();
+    sections.sort_by_key(|section| section.address);
+    let mut code = Vec::new();
+    let mut data = Vec::new();
+    for section in sections {
+        let bytes = &section.bytes[..];
+        let size = match section.kind {
+            SectionKind::Code => {
+                let (instructions, size) = decode(bytes, section.address);
+                code.push(instructions);
+                size
+            }
+            SectionKind::Data => 0,
+        };
+        if size < bytes.len() {
+            data.push((section.address + size, &bytes[size..]));
+        }
+    }
+    render(&code, &data)
+}
+
+/// Instructions decoded from the start of `bytes`, loaded at `address`,
+/// until the first byte sequence which is not a valid instruction, with
+/// the number of bytes they take.
+fn decode(bytes: &[u8], address: usize) -> (Vec<(usize, Instruction)>, usize) {
+    let mut code = Vec::new();
+    let mut offset = 0;
+    while let Ok((instruction, size)) = Instruction::decode(&bytes[offset..]) {
+        code.push((address + offset, instruction));
+        offset += size;
+    }
+    (code, offset)
+}
+
+/// Listing of blocks of consecutive instructions and of data areas, given
+/// with their addresses.
+fn render(code: &[Vec<(usize, Instruction)>], data: &[(usize, &[u8])]) -> String {
+    let in_data = |address: usize| {
+        data.iter()
+            .any(|(start, bytes)| (*start..start + bytes.len()).contains(&address))
+    };
+
+    // Find out which loadimm instructions load a reference to a jump target
+    // or to data, by following constants until they are used
+    let mut references = BTreeMap::new();
+    for block in code {
+        let mut constants: [Option<(usize, i16)>; 16] = [None; 16];
+        for (address, instruction) in block {
+            match *instruction {
+                Instruction::LoadImm { rd, imm: value } => {
+                    if rd == 0 || (value >= 0 && in_data(value as usize)) {
+                        references.insert(*address, value as usize);
+                    }
+                    constants[rd as usize] = Some((*address, value));
+                }
+                Instruction::Call { target } => {
+                    references.insert(*address, target as usize);
+                }
+                Instruction::Branch { offset, .. } => {
+                    if let Some(target) = address.checked_add_signed(offset as isize) {
+                        references.insert(*address, target);
+                    }
+                }
+                Instruction::MoveIf { rd: 0, rs, .. }
+                | Instruction::Store { rs, .. }
+                | Instruction::Push { rs } => {
+                    if let Some((loadimm, value)) = constants[rs as usize] {
+                        references.insert(loadimm, value as usize);
+                    }
+                }
+                Instruction::MoveIf { rd, .. }
+                | Instruction::Load { rd, .. }
+                | Instruction::Sub { rd, .. }
+                | Instruction::Binary { rd, .. }
+                | Instruction::Not { rd, .. }
+                | Instruction::Pop { rd }
+                | Instruction::In { rd }
+                | Instruction::InNumber { rd } => {
+                    constants[rd as usize] = None;
+                }
+                _ => (),
+            }
+        }
+    }
+    // Only keep the references which point to an instruction or into the
+    // data, as stored constants may be plain numbers
+    let boundaries: BTreeSet<usize> = code.iter().flatten().map(|(address, _)| *address).collect();
+    references.retain(|_, target| boundaries.contains(target) || in_data(*target));
+    let labels: BTreeMap<usize, String> = references
+        .values()
+        .map(|&target| {
+            let kind = if boundaries.contains(&target) {
+                "label"
+            } else {
+                "data"
+            };
+            (target, format!("{kind}_{target:04}"))
+        })
+        .collect();
+
+    let mut lines = BTreeMap::new();
+    for (address, instruction) in code.iter().flatten() {
+        let mut line = String::new();
+        if let Some(label) = labels.get(address) {
+            writeln!(line, "{label}:").unwrap();
+        }
+        match (instruction, references.get(address)) {
+            (Instruction::LoadImm { rd, .. }, Some(target)) => {
+                writeln!(
+                    line,
+                    "  {address:04}   loadimm r{rd} <- #{}",
+                    labels[target]
+                )
+            }
+            (Instruction::Call { .. }, Some(target)) => {
+                writeln!(line, "  {address:04}   call #{}", labels[target])
+            }
+            (Instruction::Branch { cond, rs1, rs2, .. }, Some(target)) => writeln!(
+                line,
+                "  {address:04}   {} r{rs1}, r{rs2}, #{}",
+                cond.mnemonic(),
+                labels[target]
+            ),
+            _ => writeln!(line, "  {address:04}   {instruction}"),
+        }
+        .unwrap();
+        lines.insert(*address, (line, instruction.size()));
+    }
+
+    // Split the data at each label so that every chunk can be referenced
+    for &(start, bytes) in data {
+        let end = start + bytes.len();
+        let mut bounds: Vec<usize> = labels.range(start..end).map(|(&a, _)| a).collect();
+        bounds.insert(0, start);
+        bounds.push(end);
+        bounds.dedup();
+        for chunk in bounds.windows(2) {
+            let mut line = String::new();
+            if let Some(label) = labels.get(&chunk[0]) {
+                writeln!(line, "{label}:").unwrap();
+            }
+            let bytes = &bytes[chunk[0] - start..chunk[1] - start];
+            if bytes.iter().all(|&b| b == 0) {
+                writeln!(line, "  ???? {bytes:?}").unwrap();
+            } else {
+                writeln!(line, "  ???? {}", byte_string(bytes)).unwrap();
+            }
+            lines.insert(chunk[0], (line, bytes.len()));
+        }
+    }
+
+    let mut listing = String::new();
+    let mut next = 0;
+    for (address, (line, size)) in lines {
+        if address != next {
+            writeln!(listing, ".org {address}").unwrap();
+        }
+        listing.push_str(&line);
+        next = address + size;
+    }
+    listing
+}
+
+/// Render bytes as a `b'...'` literal, quoted and escaped the way Python does.
+fn byte_string(bytes: &[u8]) -> String {
+    let quote = if bytes.contains(&b'\'') && !bytes.contains(&b'"') {
+        '"'
+    } else {
+        '\''
+    };
+    let mut literal = format!("b{quote}");
+    for &byte in bytes {
+        match byte {
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\r' => literal.push_str("\\r"),
+            b'\t' => literal.push_str("\\t"),
+            _ if byte as char == quote => {
+                literal.push('\\');
+                literal.push(quote);
+            }
+            0x20..=0x7e => literal.push(byte as char),
+            _ => write!(literal, "\\x{byte:02x}").unwrap(),
+        }
+    }
+    literal.push(quote);
+    literal
+}
@@ -0,0 +1,126 @@
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Attempt to create a machine with too large a memory, `size` being
+    /// the memory needed for the program including its load address
+    ProgramTooLarge { size: usize, memory_size: usize },
+    /// Access to memory at `address`, which is out of bounds
+    MemoryOverflow { address: usize },
+    /// Access to register `reg`, which does not exist
+    RegistreOverdepass { reg: usize },
+    /// Failure to write the output of the program
+    OutputError(io::Error),
+    /// Failure to read the input of the program, or malformed number
+    InputError(io::Error),
+    /// Unknown opcode
+    InstructionError { opcode: u8 },
+    /// Instruction whose encoding crosses the end of memory
+    TruncatedInstruction,
+    /// Division or remainder by zero
+    DivisionByZero,
+    /// Mapping of a device (or of the interrupt controller) at `address`
+    /// over another device
+    DeviceOverlap { address: usize },
+    /// Load or store refused by a device
+    UnsupportedAccess,
+    /// Snapshot which cannot be restored, with the reason
+    InvalidSnapshot(String),
+    /// Object file which cannot be loaded, with the reason
+    InvalidObject(String),
+    /// Symbol referenced by an object file but defined nowhere
+    UndefinedSymbol(String),
+    /// Symbol referenced by an object file and defined by several others
+    AmbiguousSymbol(String),
+    /// Address of `symbol` not fitting in the instruction at `address`
+    RelocationOverflow { symbol: String, address: usize },
+    /// Push below address 0, `sp` being the stack pointer (r2)
+    StackOverflow { sp: usize },
+    /// Pop from an empty stack, `sp` being the stack pointer (r2), which
+    /// is at or past the end of memory
+    StackUnderflow { sp: usize },
+    /// Error raised while executing the instruction at `ip`, whose opcode
+    /// is `opcode` (or `None` if `ip` is outside of memory)
+    Fault {
+        ip: usize,
+        opcode: Option<u8>,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Address of the faulting instruction, if the error happened during
+    /// execution.
+    #[must_use]
+    pub fn ip(&self) -> Option<usize> {
+        match self {
+            Error::Fault { ip, .. } => Some(*ip),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without the location of the faulting instruction.
+    #[must_use]
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::Fault { source, .. } => source.cause(),
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ProgramTooLarge { size, memory_size } => write!(
+                f,
+                "program needing {size} bytes does not fit in memory ({memory_size} bytes)"
+            ),
+            Error::MemoryOverflow { address } => {
+                write!(f, "memory access at address {address} is out of bounds")
+            }
+            Error::RegistreOverdepass { reg } => write!(f, "register r{reg} does not exist"),
+            Error::OutputError(e) => write!(f, "cannot write output: {e}"),
+            Error::InputError(e) => write!(f, "cannot read input: {e}"),
+            Error::InstructionError { opcode } => write!(f, "invalid opcode {opcode}"),
+            Error::TruncatedInstruction => write!(f, "instruction crosses the end of memory"),
+            Error::DivisionByZero => write!(f, "division by zero"),
+            Error::DeviceOverlap { address } => {
+                write!(f, "device at address {address} overlaps another device")
+            }
+            Error::UnsupportedAccess => write!(f, "access not supported by the device"),
+            Error::InvalidSnapshot(reason) => write!(f, "invalid snapshot: {reason}"),
+            Error::InvalidObject(reason) => write!(f, "invalid object file: {reason}"),
+            Error::UndefinedSymbol(symbol) => write!(f, "undefined symbol `{symbol}`"),
+            Error::AmbiguousSymbol(symbol) => {
+                write!(f, "symbol `{symbol}` is defined by several object files")
+            }
+            Error::RelocationOverflow { symbol, address } => write!(
+                f,
+                "address of `{symbol}` does not fit in the instruction at address {address}"
+            ),
+            Error::StackOverflow { sp } => write!(f, "stack overflow (r2 = {sp})"),
+            Error::StackUnderflow { sp } => write!(f, "stack underflow (r2 = {sp})"),
+            Error::Fault {
+                ip,
+                opcode: Some(opcode),
+                source,
+            } => write!(f, "at address {ip:04} (opcode {opcode}): {source}"),
+            Error::Fault {
+                ip,
+                opcode: None,
+                source,
+            } => write!(f, "at address {ip:04}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OutputError(e) | Error::InputError(e) => Some(e),
+            Error::Fault { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use crate::snapshot::invalid;
+use crate::{Device, Error};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Address where the CLI maps the framebuffer, just after the default
+/// memory.
+pub const FRAMEBUFFER_ADDRESS: usize = 0x1000;
+
+/// Number of bytes of an 8×8 RGB frame.
+pub const FRAME_SIZE: usize = 192;
+
+/// Frame laid out like `tp_led_matrix::Image::as_ref()`: 64 pixels row
+/// after row, each one being its red, green and blue bytes.
+pub type Frame = [u8; FRAME_SIZE];
+
+/// Function receiving each presented frame along with its number.
+type Render = Box<dyn FnMut(&Frame, u32) -> io::Result<()>>;
+
+/// 8×8 RGB framebuffer. The frame takes the first 192 bytes of the device,
+/// and storing any value in the present register which follows hands the
+/// frame over to the renderer. Loading the present register gives the
+/// number of frames presented so far.
+pub struct Framebuffer {
+    frame: Frame,
+    presented: u32,
+    render: Render,
+}
+
+impl Framebuffer {
+    /// Create a framebuffer calling `render` with each presented frame and
+    /// its number, starting at 0.
+    pub fn new(render: impl FnMut(&Frame, u32) -> io::Result<()> + 'static) -> Self {
+        Framebuffer {
+            frame: [0; FRAME_SIZE],
+            presented: 0,
+            render: Box::new(render),
+        }
+    }
+
+    /// Create a framebuffer drawing the frames on a terminal supporting
+    /// true colors, each frame replacing the previous one.
+    pub fn terminal(mut output: impl Write + 'static) -> Self {
+        Framebuffer::new(move |frame, number| {
+            if number > 0 {
+                // Go back to the top of the previous frame
+                write!(output, "\x1b[8A")?;
+            }
+            output.write_all(render_ansi(frame).as_bytes())?;
+            output.flush()
+        })
+    }
+
+    /// Create a framebuffer writing each frame as a PPM image named
+    /// `frame_NNNN.ppm` in `dir`, which is created if needed.
+    pub fn ppm(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Framebuffer::new(move |frame, number| {
+            fs::create_dir_all(&dir)?;
+            fs::write(
+                dir.join(format!("frame_{number:04}.ppm")),
+                render_ppm(frame),
+            )
+        })
+    }
+}
+
+impl Device for Framebuffer {
+    fn size(&self) -> usize {
+        FRAME_SIZE + 4
+    }
+
+    fn load(&mut self, offset: usize) -> Result<u32> {
+        if offset == FRAME_SIZE {
+            return Ok(self.presented);
+        }
+        // Bytes past the frame read as 0
+        let mut word = [0; 4];
+        for (i, byte) in word.iter_mut().enumerate() {
+            *byte = self.frame.get(offset + i).copied().unwrap_or(0);
+        }
+        Ok(u32::from_le_bytes(word))
+    }
+
+    fn store(&mut self, offset: usize, value: u32) -> Result<()> {
+        if offset == FRAME_SIZE {
+            (self.render)(&self.frame, self.presented).map_err(Error::OutputError)?;
+            self.presented += 1;
+            return Ok(());
+        }
+        // Bytes past the frame are dropped
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            if let Some(pixel) = self.frame.get_mut(offset + i) {
+                *pixel = byte;
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Vec<u8> {
+        let mut state = self.frame.to_vec();
+        state.extend(self.presented.to_le_bytes());
+        state
+    }
+
+    fn restore(&mut self, state: &[u8]) -> Result<()> {
+        if state.len() != FRAME_SIZE + 4 {
+            return Err(invalid("invalid framebuffer state"));
+        }
+        let (frame, presented) = state.split_at(FRAME_SIZE);
+        self.frame.copy_from_slice(frame);
+        self.presented = u32::from_le_bytes(presented.try_into().unwrap());
+        Ok(())
+    }
+}
+
+/// Render a frame as 8 lines of ANSI true color blocks.
+#[must_use]
+pub fn render_ansi(frame: &Frame) -> String {
+    let mut text = String::new();
+    for row in frame.chunks(24) {
+        for pixel in row.chunks(3) {
+            write!(text, "\x1b[48;2;{};{};{}m  ", pixel[0], pixel[1], pixel[2]).unwrap();
+        }
+        text += "\x1b[0m\n";
+    }
+    text
+}
+
+/// Render a frame as a binary PPM image.
+#[must_use]
+pub fn render_ppm(frame: &Frame) -> Vec<u8> {
+    let mut image = b"P6\n8 8\n255\n".to_vec();
+    image.extend_from_slice(frame);
+    image
+}
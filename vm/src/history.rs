@@ -0,0 +1,54 @@
+use crate::InterruptController;
+use crate::machine::NREGS;
+use std::collections::VecDeque;
+
+/// State needed to undo one executed instruction.
+pub(crate) struct UndoRecord {
+    pub(crate) registers: [u32; NREGS],
+    pub(crate) interrupts: InterruptController,
+    pub(crate) steps: u64,
+    /// Memory bytes overwritten, with their address and previous value
+    pub(crate) memory: Vec<(usize, u8)>,
+}
+
+/// Undo journal of the last executed instructions, the oldest ones being
+/// forgotten once `limit` records are kept.
+pub(crate) struct History {
+    limit: usize,
+    records: VecDeque<UndoRecord>,
+}
+
+impl History {
+    pub(crate) fn new(limit: usize) -> Self {
+        History {
+            limit,
+            records: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.records.len() > limit {
+            self.records.pop_front();
+        }
+    }
+
+    pub(crate) fn push(&mut self, record: UndoRecord) {
+        if self.records.len() == self.limit {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+}
@@ -0,0 +1,473 @@
+use crate::Error;
+use std::fmt;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A decoded instruction of the machine. Register operands are numbers
+/// between 0 and 15, r0 being the instruction pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `move rd <- rs if rc != 0` (opcode 1)
+    MoveIf { rd: u8, rs: u8, rc: u8 },
+    /// `store [ra] <- rs` (opcode 2), writing a little-endian u32
+    Store { ra: u8, rs: u8 },
+    /// `load rd <- [ra]` (opcode 3), reading a little-endian u32
+    Load { rd: u8, ra: u8 },
+    /// `loadimm rd <- #imm` (opcode 4), sign-extending the immediate
+    LoadImm { rd: u8, imm: i16 },
+    /// `sub rd <- rs1 - rs2` (opcode 5)
+    Sub { rd: u8, rs1: u8, rs2: u8 },
+    /// `out rs` (opcode 6), printing the low byte of `rs` as a character
+    Out { rs: u8 },
+    /// `exit` (opcode 7)
+    Exit,
+    /// `out_number rs` (opcode 8), printing `rs` as a signed number
+    OutNumber { rs: u8 },
+    /// `in rd` (opcode 9), reading one byte, or -1 at the end of input
+    In { rd: u8 },
+    /// `in_number rd` (opcode 10), reading a signed decimal number
+    InNumber { rd: u8 },
+    /// `<op> rd <- rs1 <symbol> rs2` (opcodes 11 to 19 and 21 to 23)
+    Binary {
+        op: BinaryOp,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    },
+    /// `not rd <- ~rs` (opcode 20), inverting every bit
+    Not { rd: u8, rs: u8 },
+    /// `push rs` (opcode 24), decrementing r2 by 4 then storing `rs` at [r2]
+    Push { rs: u8 },
+    /// `pop rd` (opcode 25), loading `rd` from [r2] then incrementing r2 by 4
+    Pop { rd: u8 },
+    /// `call #target` (opcode 26), pushing the address of the next
+    /// instruction and jumping to `target`
+    Call { target: u16 },
+    /// `ret` (opcode 27), popping the return address into the IP
+    Ret,
+    /// `iret` (opcode 35), returning from an interrupt handler
+    Iret,
+    /// `<cond> rs1, rs2, #offset` (opcodes 31 to 34), adding `offset` to
+    /// the address of the branch itself when the condition holds
+    Branch {
+        cond: Condition,
+        rs1: u8,
+        rs2: u8,
+        offset: i16,
+    },
+}
+
+/// Operation of a three-register arithmetic, logic or comparison
+/// instruction, encoded like `sub`. Shift amounts only use the low 5 bits
+/// of `rs2`, and comparisons give 1 when true and 0 when false.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    /// `add rd <- rs1 + rs2` (opcode 11)
+    Add,
+    /// `mul rd <- rs1 * rs2` (opcode 12), keeping the low 32 bits
+    Mul,
+    /// `div rd <- rs1 / rs2` (opcode 13), signed and rounding toward zero
+    Div,
+    /// `divu rd <- rs1 / rs2` (opcode 14), unsigned
+    Divu,
+    /// `rem rd <- rs1 % rs2` (opcode 15), signed, with the sign of `rs1`
+    Rem,
+    /// `remu rd <- rs1 % rs2` (opcode 16), unsigned
+    Remu,
+    /// `and rd <- rs1 & rs2` (opcode 17)
+    And,
+    /// `or rd <- rs1 | rs2` (opcode 18)
+    Or,
+    /// `xor rd <- rs1 ^ rs2` (opcode 19)
+    Xor,
+    /// `shl rd <- rs1 << rs2` (opcode 21)
+    Shl,
+    /// `shr rd <- rs1 >> rs2` (opcode 22), shifting zeroes in
+    Shr,
+    /// `sar rd <- rs1 >> rs2` (opcode 23), replicating the sign bit
+    Sar,
+    /// `slt rd <- rs1 < rs2` (opcode 28), signed
+    Slt,
+    /// `sltu rd <- rs1 < rs2` (opcode 29), unsigned
+    Sltu,
+    /// `seq rd <- rs1 == rs2` (opcode 30)
+    Seq,
+}
+
+impl BinaryOp {
+    /// Every operation, in opcode order.
+    pub const ALL: [BinaryOp; 15] = [
+        BinaryOp::Add,
+        BinaryOp::Mul,
+        BinaryOp::Div,
+        BinaryOp::Divu,
+        BinaryOp::Rem,
+        BinaryOp::Remu,
+        BinaryOp::And,
+        BinaryOp::Or,
+        BinaryOp::Xor,
+        BinaryOp::Shl,
+        BinaryOp::Shr,
+        BinaryOp::Sar,
+        BinaryOp::Slt,
+        BinaryOp::Sltu,
+        BinaryOp::Seq,
+    ];
+
+    /// Opcode of the instruction doing this operation.
+    #[must_use]
+    pub fn opcode(self) -> u8 {
+        match self {
+            BinaryOp::Add => 11,
+            BinaryOp::Mul => 12,
+            BinaryOp::Div => 13,
+            BinaryOp::Divu => 14,
+            BinaryOp::Rem => 15,
+            BinaryOp::Remu => 16,
+            BinaryOp::And => 17,
+            BinaryOp::Or => 18,
+            BinaryOp::Xor => 19,
+            BinaryOp::Shl => 21,
+            BinaryOp::Shr => 22,
+            BinaryOp::Sar => 23,
+            BinaryOp::Slt => 28,
+            BinaryOp::Sltu => 29,
+            BinaryOp::Seq => 30,
+        }
+    }
+
+    /// Name of the instruction in listings.
+    #[must_use]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "add",
+            BinaryOp::Mul => "mul",
+            BinaryOp::Div => "div",
+            BinaryOp::Divu => "divu",
+            BinaryOp::Rem => "rem",
+            BinaryOp::Remu => "remu",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::Xor => "xor",
+            BinaryOp::Shl => "shl",
+            BinaryOp::Shr => "shr",
+            BinaryOp::Sar => "sar",
+            BinaryOp::Slt => "slt",
+            BinaryOp::Sltu => "sltu",
+            BinaryOp::Seq => "seq",
+        }
+    }
+
+    /// Operator written between the source registers in listings.
+    #[must_use]
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div | BinaryOp::Divu => "/",
+            BinaryOp::Rem | BinaryOp::Remu => "%",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+            BinaryOp::Xor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr | BinaryOp::Sar => ">>",
+            BinaryOp::Slt | BinaryOp::Sltu => "<",
+            BinaryOp::Seq => "==",
+        }
+    }
+
+    /// Compute `a <op> b`, or return `None` when dividing by zero. Signed
+    /// overflow wraps around, so `i32::MIN / -1` is `i32::MIN`.
+    #[must_use]
+    pub fn apply(self, a: u32, b: u32) -> Option<u32> {
+        let value = match self {
+            BinaryOp::Add => a.wrapping_add(b),
+            BinaryOp::Mul => a.wrapping_mul(b),
+            BinaryOp::Div | BinaryOp::Divu | BinaryOp::Rem | BinaryOp::Remu if b == 0 => {
+                return None;
+            }
+            BinaryOp::Div => (a as i32).wrapping_div(b as i32) as u32,
+            BinaryOp::Divu => a / b,
+            BinaryOp::Rem => (a as i32).wrapping_rem(b as i32) as u32,
+            BinaryOp::Remu => a % b,
+            BinaryOp::And => a & b,
+            BinaryOp::Or => a | b,
+            BinaryOp::Xor => a ^ b,
+            BinaryOp::Shl => a << (b & 31),
+            BinaryOp::Shr => a >> (b & 31),
+            BinaryOp::Sar => ((a as i32) >> (b & 31)) as u32,
+            BinaryOp::Slt => u32::from((a as i32) < (b as i32)),
+            BinaryOp::Sltu => u32::from(a < b),
+            BinaryOp::Seq => u32::from(a == b),
+        };
+        Some(value)
+    }
+}
+
+/// Condition of a relative branch, comparing two registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// `beq` (opcode 31), branching if `rs1 == rs2`
+    Eq,
+    /// `bne` (opcode 32), branching if `rs1 != rs2`
+    Ne,
+    /// `blt` (opcode 33), branching if `rs1 < rs2`, signed
+    Lt,
+    /// `bge` (opcode 34), branching if `rs1 >= rs2`, signed
+    Ge,
+}
+
+impl Condition {
+    /// Every condition, in opcode order.
+    pub const ALL: [Condition; 4] = [Condition::Eq, Condition::Ne, Condition::Lt, Condition::Ge];
+
+    /// Opcode of the branch testing this condition.
+    #[must_use]
+    pub fn opcode(self) -> u8 {
+        match self {
+            Condition::Eq => 31,
+            Condition::Ne => 32,
+            Condition::Lt => 33,
+            Condition::Ge => 34,
+        }
+    }
+
+    /// Name of the branch in listings.
+    #[must_use]
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Condition::Eq => "beq",
+            Condition::Ne => "bne",
+            Condition::Lt => "blt",
+            Condition::Ge => "bge",
+        }
+    }
+
+    /// Whether the branch is taken for these register values.
+    #[must_use]
+    pub fn holds(self, a: u32, b: u32) -> bool {
+        match self {
+            Condition::Eq => a == b,
+            Condition::Ne => a != b,
+            Condition::Lt => (a as i32) < (b as i32),
+            Condition::Ge => (a as i32) >= (b as i32),
+        }
+    }
+}
+
+impl Instruction {
+    /// Size in bytes of the longest instruction.
+    pub const MAX_SIZE: usize = 5;
+
+    /// Decode the instruction at the start of `code`, returning it along
+    /// with its size in bytes.
+    ///
+    /// # Errors
+    /// This function returns an error if the opcode is unknown, if the
+    /// instruction does not fit in `code`, or if a register operand is
+    /// above 15.
+    pub fn decode(code: &[u8]) -> Result<(Instruction, usize)> {
+        let opcode = *code.first().ok_or(Error::TruncatedInstruction)?;
+        let size = Instruction::size_of(opcode).ok_or(Error::InstructionError { opcode })?;
+        let bytes = code.get(..size).ok_or(Error::TruncatedInstruction)?;
+        let reg = |index: usize| {
+            let reg = bytes[index];
+            if reg > 15 {
+                return Err(Error::RegistreOverdepass { reg: reg as usize });
+            }
+            Ok(reg)
+        };
+        let instruction = match bytes[0] {
+            1 => Instruction::MoveIf {
+                rd: reg(1)?,
+                rs: reg(2)?,
+                rc: reg(3)?,
+            },
+            2 => Instruction::Store {
+                ra: reg(1)?,
+                rs: reg(2)?,
+            },
+            3 => Instruction::Load {
+                rd: reg(1)?,
+                ra: reg(2)?,
+            },
+            4 => Instruction::LoadImm {
+                rd: reg(1)?,
+                imm: i16::from_le_bytes([bytes[2], bytes[3]]),
+            },
+            5 => Instruction::Sub {
+                rd: reg(1)?,
+                rs1: reg(2)?,
+                rs2: reg(3)?,
+            },
+            6 => Instruction::Out { rs: reg(1)? },
+            7 => Instruction::Exit,
+            8 => Instruction::OutNumber { rs: reg(1)? },
+            9 => Instruction::In { rd: reg(1)? },
+            10 => Instruction::InNumber { rd: reg(1)? },
+            20 => Instruction::Not {
+                rd: reg(1)?,
+                rs: reg(2)?,
+            },
+            24 => Instruction::Push { rs: reg(1)? },
+            25 => Instruction::Pop { rd: reg(1)? },
+            26 => Instruction::Call {
+                target: u16::from_le_bytes([bytes[1], bytes[2]]),
+            },
+            27 => Instruction::Ret,
+            35 => Instruction::Iret,
+            31..=34 => Instruction::Branch {
+                cond: Condition::ALL[usize::from(bytes[0] - 31)],
+                rs1: reg(1)?,
+                rs2: reg(2)?,
+                offset: i16::from_le_bytes([bytes[3], bytes[4]]),
+            },
+            opcode => Instruction::Binary {
+                op: BinaryOp::ALL
+                    .into_iter()
+                    .find(|op| op.opcode() == opcode)
+                    .ok_or(Error::InstructionError { opcode })?,
+                rd: reg(1)?,
+                rs1: reg(2)?,
+                rs2: reg(3)?,
+            },
+        };
+        Ok((instruction, size))
+    }
+
+    /// Size in bytes of the instructions with this opcode, if it exists.
+    #[must_use]
+    pub fn size_of(opcode: u8) -> Option<usize> {
+        match opcode {
+            31..=34 => Some(5),
+            1 | 4 | 5 | 11..=19 | 21..=23 | 28..=30 => Some(4),
+            2 | 3 | 20 | 26 => Some(3),
+            6 | 8 | 9 | 10 | 24 | 25 => Some(2),
+            7 | 27 | 35 => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Encode the instruction into its binary representation.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            Instruction::MoveIf { rd, rs, rc } => vec![1, rd, rs, rc],
+            Instruction::Store { ra, rs } => vec![2, ra, rs],
+            Instruction::Load { rd, ra } => vec![3, rd, ra],
+            Instruction::LoadImm { rd, imm } => {
+                let [low, high] = imm.to_le_bytes();
+                vec![4, rd, low, high]
+            }
+            Instruction::Sub { rd, rs1, rs2 } => vec![5, rd, rs1, rs2],
+            Instruction::Out { rs } => vec![6, rs],
+            Instruction::Exit => vec![7],
+            Instruction::OutNumber { rs } => vec![8, rs],
+            Instruction::In { rd } => vec![9, rd],
+            Instruction::InNumber { rd } => vec![10, rd],
+            Instruction::Binary { op, rd, rs1, rs2 } => vec![op.opcode(), rd, rs1, rs2],
+            Instruction::Not { rd, rs } => vec![20, rd, rs],
+            Instruction::Push { rs } => vec![24, rs],
+            Instruction::Pop { rd } => vec![25, rd],
+            Instruction::Call { target } => {
+                let [low, high] = target.to_le_bytes();
+                vec![26, low, high]
+            }
+            Instruction::Ret => vec![27],
+            Instruction::Iret => vec![35],
+            Instruction::Branch {
+                cond,
+                rs1,
+                rs2,
+                offset,
+            } => {
+                let [low, high] = offset.to_le_bytes();
+                vec![cond.opcode(), rs1, rs2, low, high]
+            }
+        }
+    }
+
+    /// Name of the instruction in listings.
+    #[must_use]
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::MoveIf { .. } => "move",
+            Instruction::Store { .. } => "store",
+            Instruction::Load { .. } => "load",
+            Instruction::LoadImm { .. } => "loadimm",
+            Instruction::Sub { .. } => "sub",
+            Instruction::Out { .. } => "out",
+            Instruction::Exit => "exit",
+            Instruction::OutNumber { .. } => "out_number",
+            Instruction::In { .. } => "in",
+            Instruction::InNumber { .. } => "in_number",
+            Instruction::Binary { op, .. } => op.mnemonic(),
+            Instruction::Not { .. } => "not",
+            Instruction::Push { .. } => "push",
+            Instruction::Pop { .. } => "pop",
+            Instruction::Call { .. } => "call",
+            Instruction::Ret => "ret",
+            Instruction::Iret => "iret",
+            Instruction::Branch { cond, .. } => cond.mnemonic(),
+        }
+    }
+
+    /// Size of the encoded instruction in bytes.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::Branch { .. } => 5,
+            Instruction::MoveIf { .. }
+            | Instruction::LoadImm { .. }
+            | Instruction::Sub { .. }
+            | Instruction::Binary { .. } => 4,
+            Instruction::Store { .. }
+            | Instruction::Load { .. }
+            | Instruction::Not { .. }
+            | Instruction::Call { .. } => 3,
+            Instruction::Out { .. }
+            | Instruction::OutNumber { .. }
+            | Instruction::In { .. }
+            | Instruction::InNumber { .. }
+            | Instruction::Push { .. }
+            | Instruction::Pop { .. } => 2,
+            Instruction::Exit | Instruction::Ret | Instruction::Iret => 1,
+        }
+    }
+}
+
+/// Format the instruction using the syntax of the `.dis` listings.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::MoveIf { rd, rs, rc } => write!(f, "move r{rd} <- r{rs} if r{rc} != 0"),
+            Instruction::Store { ra, rs } => write!(f, "store [r{ra}] <- r{rs}"),
+            Instruction::Load { rd, ra } => write!(f, "load r{rd} <- [r{ra}]"),
+            Instruction::LoadImm { rd, imm } => write!(f, "loadimm r{rd} <- #{imm}"),
+            Instruction::Sub { rd, rs1, rs2 } => write!(f, "sub r{rd} <- r{rs1} - r{rs2}"),
+            Instruction::Out { rs } => write!(f, "out r{rs}"),
+            Instruction::Exit => write!(f, "exit"),
+            Instruction::OutNumber { rs } => write!(f, "out_number r{rs}"),
+            Instruction::In { rd } => write!(f, "in r{rd}"),
+            Instruction::InNumber { rd } => write!(f, "in_number r{rd}"),
+            Instruction::Binary { op, rd, rs1, rs2 } => write!(
+                f,
+                "{} r{rd} <- r{rs1} {} r{rs2}",
+                op.mnemonic(),
+                op.symbol()
+            ),
+            Instruction::Not { rd, rs } => write!(f, "not r{rd} <- ~r{rs}"),
+            Instruction::Push { rs } => write!(f, "push r{rs}"),
+            Instruction::Pop { rd } => write!(f, "pop r{rd}"),
+            Instruction::Call { target } => write!(f, "call #{target}"),
+            Instruction::Ret => write!(f, "ret"),
+            Instruction::Iret => write!(f, "iret"),
+            Instruction::Branch {
+                cond,
+                rs1,
+                rs2,
+                offset,
+            } => write!(f, "{} r{rs1}, r{rs2}, #{offset}", cond.mnemonic()),
+        }
+    }
+}
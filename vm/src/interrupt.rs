@@ -0,0 +1,166 @@
+use crate::Error;
+use crate::snapshot::{Decoder, Encoder};
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Address where the CLI maps the interrupt controller, after the
+/// framebuffer.
+pub const INTERRUPT_CONTROLLER_ADDRESS: usize = 0x1100;
+
+/// Number of bytes taken by the registers of the interrupt controller.
+pub const INTERRUPT_CONTROLLER_SIZE: usize = 20;
+
+/// Interrupt controller of a [`Machine`](crate::Machine), with a timer
+/// counting executed instructions.
+///
+/// Once mapped with
+/// [`Machine::map_interrupt_controller`](crate::Machine::map_interrupt_controller),
+/// its registers are the following words:
+///
+/// | Offset | Register                                                     |
+/// |--------|--------------------------------------------------------------|
+/// | 0      | interrupt enable (bit 0)                                     |
+/// | 4      | vector, the address of the handler                           |
+/// | 8      | IP saved when the interrupt was taken                        |
+/// | 12     | timer period in instructions (0 stops the timer)             |
+/// | 16     | instructions counted since the timer last fired (read-only)  |
+///
+/// Each instruction is counted before being executed, so that a period of
+/// N fires once the N instructions following the one setting it are
+/// executed. When the timer fires, the interrupt stays pending until interrupts are
+/// enabled. Taking it saves the IP, disables interrupts and jumps to the
+/// vector; `iret` restores the IP and enables interrupts again. Running
+/// `iret` outside of a handler is an error.
+#[derive(Clone, Debug, Default)]
+pub struct InterruptController {
+    enabled: bool,
+    vector: u32,
+    saved_ip: u32,
+    period: u32,
+    count: u32,
+    pending: bool,
+    in_service: bool,
+}
+
+impl InterruptController {
+    /// Whether interrupts are enabled.
+    #[must_use]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Address of the interrupt handler.
+    #[must_use]
+    pub fn vector(&self) -> u32 {
+        self.vector
+    }
+
+    /// IP saved when the last interrupt was taken.
+    #[must_use]
+    pub fn saved_ip(&self) -> u32 {
+        self.saved_ip
+    }
+
+    /// Period of the timer in instructions, 0 if it is stopped.
+    #[must_use]
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    /// Whether the timer fired and the interrupt was not taken yet.
+    #[must_use]
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Whether an interrupt was taken and its handler did not return yet.
+    #[must_use]
+    pub fn in_service(&self) -> bool {
+        self.in_service
+    }
+
+    pub(crate) fn load(&self, offset: usize) -> Result<u32> {
+        match offset {
+            0 => Ok(u32::from(self.enabled)),
+            4 => Ok(self.vector),
+            8 => Ok(self.saved_ip),
+            12 => Ok(self.period),
+            16 => Ok(self.count),
+            _ => Err(Error::UnsupportedAccess),
+        }
+    }
+
+    pub(crate) fn store(&mut self, offset: usize, value: u32) -> Result<()> {
+        match offset {
+            0 => self.enabled = value & 1 != 0,
+            4 => self.vector = value,
+            8 => self.saved_ip = value,
+            12 => {
+                // Restart counting from the new period
+                self.period = value;
+                self.count = 0;
+                self.pending = false;
+            }
+            _ => return Err(Error::UnsupportedAccess),
+        }
+        Ok(())
+    }
+
+    /// Count one executed instruction.
+    pub(crate) fn tick(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.count += 1;
+        if self.count >= self.period {
+            self.count = 0;
+            self.pending = true;
+        }
+    }
+
+    /// Take the pending interrupt if interrupts are enabled, saving `ip`
+    /// and returning the address of the handler.
+    pub(crate) fn take(&mut self, ip: u32) -> Option<u32> {
+        if !(self.pending && self.enabled) {
+            return None;
+        }
+        self.pending = false;
+        self.enabled = false;
+        self.in_service = true;
+        self.saved_ip = ip;
+        Some(self.vector)
+    }
+
+    pub(crate) fn save(&self, encoder: &mut Encoder) {
+        encoder.u8(u8::from(self.enabled));
+        encoder.u32(self.vector);
+        encoder.u32(self.saved_ip);
+        encoder.u32(self.period);
+        encoder.u32(self.count);
+        encoder.u8(u8::from(self.pending));
+        encoder.u8(u8::from(self.in_service));
+    }
+
+    pub(crate) fn restore(decoder: &mut Decoder) -> Result<Self> {
+        Ok(InterruptController {
+            enabled: decoder.u8()? != 0,
+            vector: decoder.u32()?,
+            saved_ip: decoder.u32()?,
+            period: decoder.u32()?,
+            count: decoder.u32()?,
+            pending: decoder.u8()? != 0,
+            in_service: decoder.u8()? != 0,
+        })
+    }
+
+    /// Return from the handler, giving the IP to go back to, or `None` if
+    /// no interrupt is in service.
+    pub(crate) fn iret(&mut self) -> Option<u32> {
+        if !self.in_service {
+            return None;
+        }
+        self.in_service = false;
+        self.enabled = true;
+        Some(self.saved_ip)
+    }
+}
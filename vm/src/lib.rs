@@ -1,3 +1,36 @@
+mod asm;
+mod builder;
+mod check;
+mod coverage;
+mod debugger;
+mod device;
+mod disasm;
+mod error;
+mod framebuffer;
+mod history;
+mod instruction;
+mod interrupt;
+mod link;
 mod machine;
+mod object;
+mod profile;
+mod snapshot;
+mod trace;
 
+pub use asm::*;
+pub use builder::*;
+pub use check::*;
+pub use coverage::*;
+pub use debugger::*;
+pub use device::*;
+pub use disasm::*;
+pub use error::*;
+pub use framebuffer::*;
+pub use instruction::*;
+pub use interrupt::*;
+pub use link::*;
 pub use machine::*;
+pub use object::*;
+pub use profile::*;
+pub use snapshot::SNAPSHOT_VERSION;
+pub use trace::*;
@@ -0,0 +1,101 @@
+use crate::{Error, Object, assemble_object};
+use std::collections::BTreeMap;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Sources of the routines of [`stdlib`], which follow the calling
+/// convention of the compiled programs: the return address is pushed on the
+/// stack pointed to by r2 before jumping to the routine.
+const STDLIB: [&str; 3] = [
+    include_str!("../stdlib/mult.dis"),
+    include_str!("../stdlib/print.dis"),
+    include_str!("../stdlib/print_number.dis"),
+];
+
+/// Object files of the standard library, one per routine: `print` (print
+/// the r11 bytes at address r10), `print_number` (print r10) and `mult`
+/// (multiply r11 by r12 into r11).
+#[must_use]
+pub fn stdlib() -> Vec<Object> {
+    STDLIB
+        .iter()
+        .map(|source| assemble_object(source).expect("stdlib sources assemble"))
+        .collect()
+}
+
+/// Link object files into a single program without relocations left.
+///
+/// The object files are laid out one after the other in memory, each one
+/// keeping the relative positions of its sections, followed by the object
+/// files of `library` defining symbols still undefined, which are only
+/// added when needed. References to a symbol defined by the same object
+/// file resolve to it, and references to other symbols to the object file
+/// defining them.
+///
+/// The program starts at the `_start` symbol if one object file defines
+/// it, and at the entry point of the first object file otherwise. When
+/// several object files define a symbol, the program symbols keep the first
+/// definition.
+///
+/// # Errors
+/// This function returns an error when a symbol is undefined, or defined by
+/// several object files and referenced by yet another one, or when the
+/// address of a symbol does not fit in the instructions referencing it.
+pub fn link(objects: &[Object], library: &[Object]) -> Result<Object> {
+    let mut modules = objects.to_vec();
+    let mut available: Vec<&Object> = library.iter().collect();
+    loop {
+        let defined = definitions(&modules);
+        let needed = available.iter().position(|candidate| {
+            (modules.iter().flat_map(Object::undefined)).any(|symbol| {
+                !defined.contains_key(symbol) && candidate.symbols.contains_key(symbol)
+            })
+        });
+        match needed {
+            Some(index) => modules.push(available.remove(index).clone()),
+            None => break,
+        }
+    }
+
+    let mut end: usize = 0;
+    for module in &mut modules {
+        let start = module.sections.iter().map(|s| s.address).min().unwrap_or(0);
+        // Object files placed further than the previous ones stay there
+        module.shift(end.saturating_sub(start));
+        end = (module.sections.iter().map(|s| s.end())).fold(end, usize::max);
+    }
+
+    let defined = definitions(&modules);
+    let mut program = Object::default();
+    for module in &mut modules {
+        for relocation in std::mem::take(&mut module.relocations) {
+            let address = match module.symbols.get(&relocation.symbol) {
+                Some(&address) => address,
+                None => match defined.get(&relocation.symbol).map(Vec::as_slice) {
+                    Some(&[address]) => address,
+                    Some(_) => return Err(Error::AmbiguousSymbol(relocation.symbol)),
+                    None => return Err(Error::UndefinedSymbol(relocation.symbol)),
+                },
+            };
+            module.patch(&relocation, address as i64 + i64::from(relocation.addend))?;
+        }
+        program.sections.append(&mut module.sections);
+        for (symbol, &address) in &module.symbols {
+            program.symbols.entry(symbol.clone()).or_insert(address);
+        }
+    }
+    program.entry = match defined.get("_start").map(Vec::as_slice) {
+        Some(&[start]) => start,
+        _ => modules.first().map_or(0, |module| module.entry),
+    };
+    Ok(program)
+}
+
+// Addresses where each symbol is defined by the object files
+fn definitions(modules: &[Object]) -> BTreeMap<String, Vec<usize>> {
+    let mut defined: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (symbol, &address) in modules.iter().flat_map(|module| &module.symbols) {
+        defined.entry(symbol.clone()).or_default().push(address);
+    }
+    defined
+}
@@ -1,64 +1,318 @@
-use std::io::{self, Write};
+use crate::history::{History, UndoRecord};
+use crate::snapshot::{Decoder, Encoder, invalid};
+use crate::{
+    BinaryOp, Condition, Device, Error, INTERRUPT_CONTROLLER_SIZE, Instruction,
+    InterruptController, MachineBuilder, Profile, Trace, TraceRecord,
+};
+use std::io::{self, Read, Write};
+use std::time::Instant;
 
 pub const MEMORY_SIZE: usize = 4096;
-const NREGS: usize = 16;
+pub(crate) const NREGS: usize = 16;
 
-const IP: usize = 0;
+pub(crate) const IP: usize = 0;
+/// Stack pointer used by push, pop, call and ret
+pub(crate) const SP: usize = 2;
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
-pub struct Machine {
-    memo: [u8; MEMORY_SIZE],
-    registre: [u32; NREGS],
+/// Number of steps between two checks of the deadline in a limited run.
+const DEADLINE_CHECK_PERIOD: u64 = 1024;
+
+/// Limits put on a run of the machine, to stop programs which do not
+/// terminate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Maximum number of instructions to execute
+    pub max_steps: Option<u64>,
+    /// Point in time after which the execution stops
+    pub deadline: Option<Instant>,
 }
 
-#[derive(Debug)]
-pub enum Error {
-    /// Attempt to create a machine with too large a memory
-    MemoryOverflow,
-    RegistreOverdepass,
-    OutputError,
-    InstructionError, // Add some more entries to represent different errors
+/// How a limited run ended, with the number of instructions executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The program terminated with an exit instruction
+    Exited { steps: u64 },
+    /// The maximum number of instructions was executed first
+    BudgetExhausted { steps: u64 },
+    /// The deadline passed first
+    TimedOut { steps: u64 },
+}
+
+pub struct Machine {
+    memo: Vec<u8>,
+    registre: [u32; NREGS],
+    steps: u64,
+    trace: Option<Trace>,
+    // memory bytes written by the current instruction, when tracing
+    written: Vec<(usize, u8)>,
+    // instruction decoded at each address along with its size, cleared
+    // when a store overwrites one of its bytes
+    decoded: Vec<Option<(Instruction, usize)>>,
+    // devices with the address where they are mapped
+    devices: Vec<(usize, Box<dyn Device>)>,
+    interrupts: InterruptController,
+    // address where the registers of the interrupt controller are mapped
+    interrupts_address: Option<usize>,
+    history: Option<History>,
+    profile: Option<Profile>,
+    // memory bytes overwritten by the current instruction with their
+    // previous value, when keeping a history
+    overwritten: Vec<(usize, u8)>,
 }
 
 impl Machine {
     /// Create a new machine in its reset state. The `memory` parameter will
     /// be copied at the beginning of the machine memory.
     ///
+    /// Use a [`MachineBuilder`] to choose the memory size, the initial
+    /// registers or the load address.
+    ///
     /// # Errors
     /// This function returns an error when the memory exceeds `MEMORY_SIZE`.
     pub fn new(memory: &[u8]) -> Result<Self> {
-        let size: usize = memory.len();
+        MachineBuilder::new().build(memory)
+    }
+
+    /// Create a builder to configure a new machine.
+    #[must_use]
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::new()
+    }
+
+    /// Create a machine from its initial memory and registers.
+    pub(crate) fn from_parts(memo: Vec<u8>, registre: [u32; NREGS]) -> Self {
+        Machine {
+            decoded: vec![None; memo.len()],
+            memo,
+            registre,
+            steps: 0,
+            trace: None,
+            written: Vec::new(),
+            devices: Vec::new(),
+            interrupts: InterruptController::default(),
+            interrupts_address: None,
+            history: None,
+            profile: None,
+            overwritten: Vec::new(),
+        }
+    }
+
+    /// Map `device` onto the addresses starting at `address`. Loads and
+    /// stores of words starting in this range are handled by the device,
+    /// even if the range is also part of the memory.
+    ///
+    /// # Errors
+    /// This function returns an error if the range overlaps the one of a
+    /// device already mapped.
+    pub fn map_device(&mut self, address: usize, device: Box<dyn Device>) -> Result<()> {
+        self.check_overlap(address, device.size())?;
+        self.devices.push((address, device));
+        Ok(())
+    }
 
-        if size > MEMORY_SIZE {
-            return Err(Error::MemoryOverflow);
+    /// Map the registers of the interrupt controller onto the addresses
+    /// starting at `address`, which lets the program use interrupts.
+    ///
+    /// # Errors
+    /// This function returns an error if the range overlaps the one of a
+    /// device, or if the controller is already mapped.
+    pub fn map_interrupt_controller(&mut self, address: usize) -> Result<()> {
+        if self.interrupts_address.is_some() {
+            return Err(Error::DeviceOverlap { address });
         }
+        self.check_overlap(address, INTERRUPT_CONTROLLER_SIZE)?;
+        self.interrupts_address = Some(address);
+        Ok(())
+    }
 
-        let mut mem: [u8; 4096] = [0; MEMORY_SIZE];
+    /// Interrupt controller of the machine.
+    #[must_use]
+    pub fn interrupts(&self) -> &InterruptController {
+        &self.interrupts
+    }
 
-        mem[..size].copy_from_slice(memory);
-        let mut reg = [0; NREGS];
-        reg[0] = IP as u32;
-        let ma_machine: Machine = Machine {
-            memo: mem,
-            registre: reg,
-        };
+    /// Serialize the memory, the registers, the number of executed
+    /// instructions, the interrupt controller and the state of the mapped
+    /// devices into a versioned binary snapshot. The trace is not saved.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.bytes(&self.memo);
+        for value in self.registre {
+            encoder.u32(value);
+        }
+        encoder.u64(self.steps);
+        encoder.u8(u8::from(self.interrupts_address.is_some()));
+        encoder.u32(self.interrupts_address.unwrap_or(0) as u32);
+        self.interrupts.save(&mut encoder);
+        encoder.u32(self.devices.len() as u32);
+        for (address, device) in &self.devices {
+            encoder.u32(*address as u32);
+            encoder.bytes(&device.save());
+        }
+        encoder.finish()
+    }
+
+    /// Restore the state saved by [`snapshot`](Machine::snapshot). The same
+    /// devices must have been mapped at the same addresses beforehand, as
+    /// only their state is part of the snapshot.
+    ///
+    /// # Errors
+    /// This function returns an error if the snapshot is malformed, comes
+    /// from an unsupported version, or does not match the mapped devices.
+    /// The machine is left untouched, although some devices may have been
+    /// restored already if one of them refuses its state.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<()> {
+        let mut decoder = Decoder::new(snapshot)?;
+        let memo = decoder.bytes()?.to_vec();
+        let mut registre = [0; NREGS];
+        for value in &mut registre {
+            *value = decoder.u32()?;
+        }
+        let steps = decoder.u64()?;
+        let mapped = decoder.u8()? != 0;
+        let address = decoder.u32()? as usize;
+        let interrupts = InterruptController::restore(&mut decoder)?;
+        if decoder.u32()? as usize != self.devices.len() {
+            return Err(invalid("mapped devices differ"));
+        }
+        let mut states = Vec::new();
+        for (start, _) in &self.devices {
+            if decoder.u32()? as usize != *start {
+                return Err(invalid("mapped devices differ"));
+            }
+            states.push(decoder.bytes()?);
+        }
+        decoder.end()?;
 
-        Ok(ma_machine)
-        // Implemention done
+        for ((_, device), state) in self.devices.iter_mut().zip(states) {
+            device.restore(state)?;
+        }
+        self.decoded = vec![None; memo.len()];
+        self.memo = memo;
+        self.registre = registre;
+        self.steps = steps;
+        self.interrupts = interrupts;
+        self.interrupts_address = mapped.then_some(address);
+        self.written.clear();
+        // The history does not apply to the restored state
+        if let Some(history) = &mut self.history {
+            *history = History::new(history.limit());
+        }
+        Ok(())
+    }
+
+    /// Refuse to map `size` bytes at `address` over a device or the
+    /// interrupt controller.
+    fn check_overlap(&self, address: usize, size: usize) -> Result<()> {
+        let end = address
+            .checked_add(size)
+            .ok_or(Error::DeviceOverlap { address })?;
+        let mut ranges = self
+            .devices
+            .iter()
+            .map(|(start, device)| (*start, device.size()))
+            .chain(
+                self.interrupts_address
+                    .map(|start| (start, INTERRUPT_CONTROLLER_SIZE)),
+            );
+        if ranges.any(|(start, size)| address < start + size && start < end) {
+            return Err(Error::DeviceOverlap { address });
+        }
+        Ok(())
+    }
+
+    /// Offset of the word at `address` in the registers of the interrupt
+    /// controller, if it is mapped there.
+    fn interrupts_offset(&self, address: usize) -> Option<usize> {
+        let start = self.interrupts_address?;
+        (start..start + INTERRUPT_CONTROLLER_SIZE)
+            .contains(&address)
+            .then(|| address - start)
+    }
+
+    /// Device whose range contains the word at `address`, with the offset
+    /// of the word in the range.
+    fn device_at(&mut self, address: usize) -> Option<(&mut Box<dyn Device>, usize)> {
+        self.devices
+            .iter_mut()
+            .find(|(start, device)| (*start..*start + device.size()).contains(&address))
+            .map(|(start, device)| (device, address - *start))
     }
 
     /// Run until the program terminates or until an error happens.
     /// If output instructions are run, they print on `fd`.
+    /// Input instructions find the end of input.
     pub fn run_on<T: Write>(&mut self, fd: &mut T) -> Result<()> {
-        while !self.step_on(fd)? {}
+        self.run_with(&mut io::empty(), fd)
+    }
+
+    /// Run until the program terminates or until an error happens.
+    /// If input instructions are run, they read from `input`, and
+    /// if output instructions are run, they print on `fd`.
+    pub fn run_with<R: Read, T: Write>(&mut self, input: &mut R, fd: &mut T) -> Result<()> {
+        while !self.step_with(input, fd)? {}
         Ok(())
     }
 
     /// Run until the program terminates or until an error happens.
-    /// If output instructions are run, they print on standard output.
+    /// If input instructions are run, they read from standard input, and
+    /// if output instructions are run, they print on standard output.
     pub fn run(&mut self) -> Result<()> {
-        self.run_on(&mut io::stdout().lock())
+        self.run_with(&mut io::stdin().lock(), &mut io::stdout().lock())
+    }
+
+    /// Run until the program terminates, an error happens, or one of the
+    /// `limits` is reached. Input and output are done as in
+    /// [`run_with`](Machine::run_with).
+    ///
+    /// The deadline is only checked between instructions, so an input
+    /// instruction waiting for data is not interrupted.
+    pub fn run_limited<R: Read, T: Write>(
+        &mut self,
+        input: &mut R,
+        fd: &mut T,
+        limits: Limits,
+    ) -> Result<Outcome> {
+        let mut steps = 0;
+        loop {
+            if limits.max_steps.is_some_and(|max| steps >= max) {
+                return Ok(Outcome::BudgetExhausted { steps });
+            }
+            if steps % DEADLINE_CHECK_PERIOD == 0
+                && limits
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Ok(Outcome::TimedOut { steps });
+            }
+            let end = self.step_with(input, fd)?;
+            steps += 1;
+            if end {
+                return Ok(Outcome::Exited { steps });
+            }
+        }
+    }
+
+    /// Run at most `max_steps` instructions, using the standard input and
+    /// output.
+    pub fn run_for(&mut self, max_steps: u64) -> Result<Outcome> {
+        let limits = Limits {
+            max_steps: Some(max_steps),
+            deadline: None,
+        };
+        self.run_limited(&mut io::stdin().lock(), &mut io::stdout().lock(), limits)
+    }
+
+    /// Run until `deadline` at most, using the standard input and output.
+    pub fn run_until(&mut self, deadline: Instant) -> Result<Outcome> {
+        let limits = Limits {
+            max_steps: None,
+            deadline: Some(deadline),
+        };
+        self.run_limited(&mut io::stdin().lock(), &mut io::stdout().lock(), limits)
     }
 
     /// Execute the next instruction by doing the following steps:
@@ -67,6 +321,7 @@ impl Machine {
     ///   - execute the decoded instruction
     ///
     /// If output instructions are run, they print on `fd`.
+    /// Input instructions find the end of input.
     /// If an error happens at either of those steps, an error is
     /// returned.
     ///
@@ -74,91 +329,222 @@ impl Machine {
     /// terminated (upon encountering an exit instruction), or
     /// `false` if the execution must continue.
     pub fn step_on<T: Write>(&mut self, fd: &mut T) -> Result<bool> {
-        // fetch instruction
-        let memory = self.memory();
-        let mut mem = [0; MEMORY_SIZE];
-        mem.copy_from_slice(memory);
-        let r0: usize = self.regs()[0] as usize;
-        if r0 == MEMORY_SIZE {
-            return Err(Error::InstructionError);
-        }
-        let opcode: u8 = mem[r0];
-        match opcode {
-            1 => {
-                self.set_reg(0, (r0 + 4) as u32)?;
-                self.is_last(r0 + 3)?;
-                let rd: u8 = mem[r0 + 1];
-                let rs1: u8 = mem[r0 + 2];
-                let rs2: u8 = mem[r0 + 3];
-                self.move_if(rd, rs1, rs2)?;
-                Ok(false)
-            }
-            2 => {
-                self.set_reg(0, (r0 + 3) as u32)?;
-                self.is_last(r0 + 2)?;
-                let rs1: u8 = mem[r0 + 1];
-                let rs2: u8 = mem[r0 + 2];
-                self.store(rs1, rs2)?;
-                Ok(false)
-            }
-            3 => {
-                self.set_reg(0, (r0 + 3) as u32)?;
-                self.is_last(r0 + 2)?;
-                let rs1: u8 = mem[r0 + 1];
-                let rs2: u8 = mem[r0 + 2];
-                self.load(rs1, rs2)?;
-                Ok(false)
+        self.step_with(&mut io::empty(), fd)
+    }
+
+    /// Similar to [`step_on`](Machine::step_on).
+    /// If input instructions are run, they read from `input`.
+    pub fn step_with<R: Read, T: Write>(&mut self, input: &mut R, fd: &mut T) -> Result<bool> {
+        // fetch and decode instruction
+        let ip = self.regs()[IP] as usize;
+        let opcode = self.memo.get(ip).copied();
+        let fault = |source| Error::Fault {
+            ip,
+            opcode,
+            source: Box::new(source),
+        };
+        let (instruction, size) = match self.decoded.get(ip) {
+            Some(Some(decoded)) => *decoded,
+            Some(None) => {
+                let decoded = Instruction::decode(&self.memo[ip..]).map_err(fault)?;
+                self.decoded[ip] = Some(decoded);
+                decoded
             }
-            4 => {
-                self.set_reg(0, (r0 + 4) as u32)?;
-                self.is_last(r0 + 3)?;
-                let rd: u8 = mem[r0 + 1];
-                let rs1: u8 = mem[r0 + 2];
-                let rs2: u8 = mem[r0 + 3];
-                self.loadimm(rd, rs1, rs2)?;
-                Ok(false)
+            None => return Err(fault(Error::MemoryOverflow { address: ip })),
+        };
+        let undo = self
+            .history
+            .is_some()
+            .then(|| (self.registre, self.interrupts.clone()));
+        // Count the instruction before it changes the timer period
+        if self.interrupts_address.is_some() {
+            self.interrupts.tick();
+        }
+        let before = self.registre;
+        self.written.clear();
+        self.set_reg(IP, (ip + size) as u32)?;
+        let result = self.execute(instruction, input, fd);
+        // Failed instructions are journaled too, as they may have changed
+        // the machine before failing
+        if let (Some((registers, interrupts)), Some(history)) = (undo, &mut self.history) {
+            history.push(UndoRecord {
+                registers,
+                interrupts,
+                steps: self.steps,
+                memory: std::mem::take(&mut self.overwritten),
+            });
+        }
+        let end = result.map_err(fault)?;
+        if self.trace.is_some() {
+            self.record(ip, instruction, &before).map_err(fault)?;
+        }
+        for (_, device) in &mut self.devices {
+            device.tick();
+        }
+        if self.interrupts_address.is_some()
+            && !end
+            && let Some(vector) = self.interrupts.take(self.registre[IP])
+        {
+            self.registre[IP] = vector;
+        }
+        if let Some(profile) = &mut self.profile {
+            profile.record(ip, instruction, &self.registre, &self.memo);
+        }
+        self.steps += 1;
+        Ok(end)
+    }
+
+    /// Keep the changes made by the last `length` instructions so that they
+    /// can be undone with [`step_back`](Machine::step_back), or stop keeping
+    /// them if `length` is 0. Devices are not rewound.
+    pub fn set_history(&mut self, length: usize) {
+        self.overwritten.clear();
+        match (&mut self.history, length) {
+            (_, 0) => self.history = None,
+            (Some(history), _) => history.set_limit(length),
+            (None, _) => self.history = Some(History::new(length)),
+        }
+    }
+
+    /// Number of instructions which can currently be undone.
+    #[must_use]
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Undo the last executed instruction, restoring the registers and the
+    /// memory as they were before it. Returns `false` if the history is
+    /// empty (or disabled).
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.as_mut().and_then(History::pop) else {
+            return false;
+        };
+        for &(address, byte) in record.memory.iter().rev() {
+            self.memo[address] = byte;
+            self.invalidate(address, 1);
+        }
+        self.registre = record.registers;
+        self.interrupts = record.interrupts;
+        self.steps = record.steps;
+        self.written.clear();
+        true
+    }
+
+    /// Undo instructions until the IP is back to `address`, that is until
+    /// the last time the instruction at `address` was about to be executed.
+    /// At least one instruction is undone. Returns `false`, after undoing the
+    /// whole history, if `address` is not found.
+    pub fn run_back_to(&mut self, address: usize) -> bool {
+        while self.step_back() {
+            if self.registre[IP] as usize == address {
+                return true;
             }
+        }
+        false
+    }
 
-            5 => {
-                self.set_reg(0, (r0 + 4) as u32)?;
-                self.is_last(r0 + 3)?;
-                let rd: u8 = mem[r0 + 1];
-                let rs1: u8 = mem[r0 + 2];
-                let rs2: u8 = mem[r0 + 3];
-                self.sub(rd, rs1, rs2)?;
-                println!("here");
-                Ok(false)
+    /// Emit a trace record for the instruction just executed.
+    fn record(&mut self, address: usize, instruction: Instruction, before: &[u32]) -> Result<()> {
+        let next = address + instruction.size();
+        let registers = (0..NREGS)
+            .filter(|&reg| {
+                let expected = if reg == IP { next as u32 } else { before[reg] };
+                self.registre[reg] != expected
+            })
+            .map(|reg| (reg, self.registre[reg]))
+            .collect();
+        let record = TraceRecord {
+            address,
+            instruction,
+            registers,
+            memory: std::mem::take(&mut self.written),
+        };
+        match &mut self.trace {
+            Some(Trace::Records(records)) => records.push(record),
+            Some(Trace::Writer(writer)) => {
+                writeln!(writer, "{record}").map_err(Error::OutputError)?;
             }
+            None => (),
+        }
+        Ok(())
+    }
+
+    /// Start tracing executed instructions into `trace`, or stop tracing if
+    /// `None` is given. The previous trace, if any, is returned, which is
+    /// how records kept in memory are retrieved.
+    pub fn set_trace(&mut self, trace: Option<Trace>) -> Option<Trace> {
+        self.written.clear();
+        std::mem::replace(&mut self.trace, trace)
+    }
 
-            6 => {
-                self.set_reg(0, (r0 + 2) as u32)?;
-                self.is_last(r0 + 1)?;
-                let rs1: u8 = mem[r0 + 1];
-                self.out(rs1, fd)?;
-                Ok(false)
+    /// Start counting executed instructions into `profile`, or stop counting
+    /// if `None` is given. The previous profile, if any, is returned.
+    pub fn set_profile(&mut self, profile: Option<Profile>) -> Option<Profile> {
+        std::mem::replace(&mut self.profile, profile)
+    }
+
+    /// Execute an already decoded instruction, returning `true` if the
+    /// program is terminated.
+    fn execute<R: Read, T: Write>(
+        &mut self,
+        instruction: Instruction,
+        input: &mut R,
+        fd: &mut T,
+    ) -> Result<bool> {
+        match instruction {
+            Instruction::MoveIf { rd, rs, rc } => self.move_if(rd, rs, rc)?,
+            Instruction::Store { ra, rs } => self.store(ra, rs)?,
+            Instruction::Load { rd, ra } => self.load(rd, ra)?,
+            Instruction::LoadImm { rd, imm } => self.loadimm(rd, imm)?,
+            Instruction::Sub { rd, rs1, rs2 } => self.sub(rd, rs1, rs2)?,
+            Instruction::Out { rs } => self.out(rs, fd)?,
+            Instruction::Exit => return Ok(true),
+            Instruction::OutNumber { rs } => self.out_number(rs, fd)?,
+            Instruction::In { rd } => self.input(rd, input)?,
+            Instruction::InNumber { rd } => self.input_number(rd, input)?,
+            Instruction::Binary { op, rd, rs1, rs2 } => self.binary(op, rd, rs1, rs2)?,
+            Instruction::Not { rd, rs } => self.not(rd, rs)?,
+            Instruction::Push { rs } => self.push(self.get_reg(rs as usize)?)?,
+            Instruction::Pop { rd } => {
+                let value = self.pop()?;
+                self.set_reg(rd as usize, value)?;
             }
-            7 => {
-                self.set_reg(0, (r0 + 1) as u32)?;
-                Ok(true)
+            Instruction::Call { target } => {
+                self.push(self.registre[IP])?;
+                self.set_reg(IP, u32::from(target))?;
             }
-
-            8 => {
-                self.set_reg(0, (r0 + 2) as u32)?;
-                self.is_last(r0 + 1)?;
-                let rs1: u8 = mem[r0 + 1];
-                self.out_number(rs1, fd)?;
-                Ok(false)
+            Instruction::Ret => {
+                let address = self.pop()?;
+                self.set_reg(IP, address)?;
+            }
+            Instruction::Iret => {
+                let address = self
+                    .interrupts_address
+                    .and_then(|_| self.interrupts.iret())
+                    .ok_or(Error::InstructionError { opcode: 35 })?;
+                self.set_reg(IP, address)?;
             }
-            _ => Err(Error::InstructionError),
+            Instruction::Branch {
+                cond,
+                rs1,
+                rs2,
+                offset,
+            } => self.branch(cond, rs1, rs2, offset, instruction.size())?,
         }
-
-        // decode instruction
+        Ok(false)
     }
 
     /// Similar to [`step_on`](Machine::step_on).
-    /// If output instructions are run, they print on standard output.
+    /// If input instructions are run, they read from standard input, and
+    /// if output instructions are run, they print on standard output.
     pub fn step(&mut self) -> Result<bool> {
-        self.step_on(&mut io::stdout().lock())
+        self.step_with(&mut io::stdin().lock(), &mut io::stdout().lock())
+    }
+
+    /// Number of instructions executed since the machine was created.
+    #[must_use]
+    pub fn steps(&self) -> u64 {
+        self.steps
     }
 
     /// Reference onto the machine current set of registers.
@@ -170,7 +556,7 @@ impl Machine {
     /// Sets a register to the given value.
     pub fn set_reg(&mut self, reg: usize, value: u32) -> Result<()> {
         if reg > 15 {
-            return Err(Error::RegistreOverdepass);
+            return Err(Error::RegistreOverdepass { reg });
         }
         self.registre[reg] = value;
         Ok(())
@@ -185,7 +571,7 @@ impl Machine {
     /// give a spicique registre
     pub fn get_reg(&self, reg: usize) -> Result<u32> {
         if reg > 15 {
-            return Err(Error::RegistreOverdepass);
+            return Err(Error::RegistreOverdepass { reg });
         }
         let registre = self.regs()[reg];
         Ok(registre)
@@ -193,20 +579,52 @@ impl Machine {
 
     /// store an u32 in the memory
     fn store_mem(&mut self, addres: usize, value: u32) -> Result<()> {
-        if addres + 3 > MEMORY_SIZE {
-            return Err(Error::MemoryOverflow);
+        if !self.devices.is_empty()
+            && let Some((device, offset)) = self.device_at(addres)
+        {
+            return device.store(offset, value);
+        }
+        if let Some(offset) = self.interrupts_offset(addres) {
+            return self.interrupts.store(offset, value);
+        }
+        if addres + 4 > self.memo.len() {
+            return Err(Error::MemoryOverflow { address: addres });
+        }
+        if self.history.is_some() {
+            self.overwritten
+                .extend((addres..addres + 4).map(|a| (a, self.memo[a])));
         }
         self.memo[addres] = (value & 0xFF) as u8;
         self.memo[addres + 1] = ((value >> 8) & 0xFF) as u8;
         self.memo[addres + 2] = ((value >> 16) & 0xFF) as u8;
         self.memo[addres + 3] = ((value >> 24) & 0xFF) as u8;
+        self.invalidate(addres, 4);
+        if self.trace.is_some() {
+            self.written
+                .extend((addres..addres + 4).map(|a| (a, self.memo[a])));
+        }
         Ok(())
     }
 
+    /// Forget the decoded instructions overlapping the `len` bytes
+    /// starting at `address`.
+    fn invalidate(&mut self, address: usize, len: usize) {
+        let start = address.saturating_sub(Instruction::MAX_SIZE - 1);
+        self.decoded[start..address + len].fill(None);
+    }
+
     /// load  an u32 in the memory
-    fn load_mem(&self, addres: usize) -> Result<u32> {
-        if addres + 3 > MEMORY_SIZE {
-            return Err(Error::MemoryOverflow);
+    fn load_mem(&mut self, addres: usize) -> Result<u32> {
+        if !self.devices.is_empty()
+            && let Some((device, offset)) = self.device_at(addres)
+        {
+            return device.load(offset);
+        }
+        if let Some(offset) = self.interrupts_offset(addres) {
+            return self.interrupts.load(offset);
+        }
+        if addres + 4 > self.memo.len() {
+            return Err(Error::MemoryOverflow { address: addres });
         }
         let value: u32 = self.memo[addres] as u32
             + ((self.memo[addres + 1] as u32) << 8)
@@ -241,18 +659,9 @@ impl Machine {
         Ok(())
     }
     /// instruction loadimm
-    fn loadimm(&mut self, rd: u8, rs1: u8, rs2: u8) -> Result<()> {
-        let l = rs1 as u16;
-        let mut h = rs2 as u16;
-        h <<= 8;
-
-        let somme = l + h;
-        let signed = somme as i16;
-        let mut value = somme as u32;
-        if signed < 0 {
-            value = value + ((0xFF_u32) << 24) + ((0xff_u32) << 16);
-        }
-        self.set_reg(rd as usize, value)?;
+    fn loadimm(&mut self, rd: u8, imm: i16) -> Result<()> {
+        // sign-extend the immediate to 32 bits
+        self.set_reg(rd as usize, imm as i32 as u32)?;
         Ok(())
     }
     /// instruction sub
@@ -263,33 +672,110 @@ impl Machine {
         self.set_reg(rd as usize, value)?;
         Ok(())
     }
+    /// arithmetic and logic instructions other than sub
+    fn binary(&mut self, op: BinaryOp, rd: u8, rs1: u8, rs2: u8) -> Result<()> {
+        let a = self.get_reg(rs1 as usize)?;
+        let b = self.get_reg(rs2 as usize)?;
+        let value = op.apply(a, b).ok_or(Error::DivisionByZero)?;
+        self.set_reg(rd as usize, value)
+    }
+    /// conditional branches, `size` being the size of the instruction which
+    /// the IP already skipped
+    fn branch(
+        &mut self,
+        cond: Condition,
+        rs1: u8,
+        rs2: u8,
+        offset: i16,
+        size: usize,
+    ) -> Result<()> {
+        let a = self.get_reg(rs1 as usize)?;
+        let b = self.get_reg(rs2 as usize)?;
+        if cond.holds(a, b) {
+            let address = self.registre[IP].wrapping_sub(size as u32);
+            self.set_reg(IP, address.wrapping_add(offset as i32 as u32))?;
+        }
+        Ok(())
+    }
+    /// instruction not
+    fn not(&mut self, rd: u8, rs: u8) -> Result<()> {
+        let value = self.get_reg(rs as usize)?;
+        self.set_reg(rd as usize, !value)
+    }
+    /// push a value on the stack pointed to by r2
+    fn push(&mut self, value: u32) -> Result<()> {
+        let sp = self.registre[SP] as usize;
+        let Some(top) = sp.checked_sub(4) else {
+            return Err(Error::StackOverflow { sp });
+        };
+        self.store_mem(top, value)?;
+        self.set_reg(SP, top as u32)
+    }
+    /// pop a value from the stack pointed to by r2
+    fn pop(&mut self) -> Result<u32> {
+        let sp = self.registre[SP] as usize;
+        if sp + 4 > self.memo.len() {
+            return Err(Error::StackUnderflow { sp });
+        }
+        let value = self.load_mem(sp)?;
+        self.set_reg(SP, (sp + 4) as u32)?;
+        Ok(value)
+    }
     /// instruction out
     fn out<T: Write>(&self, rs1: u8, fd: &mut T) -> Result<()> {
         let mut data = self.get_reg(rs1 as usize)?;
         data &= 0xff;
         let value = data as u8 as char;
-        let miss = write!(fd, "{value}");
-        match miss {
-            Err(_) => Err(Error::OutputError),
-            Ok(_) => Ok(()),
-        }
+        write!(fd, "{value}").map_err(Error::OutputError)
     }
     /// instruction out_number
     fn out_number<T: Write>(&self, rs1: u8, fd: &mut T) -> Result<()> {
         let data = self.get_reg(rs1 as usize)?;
         let value = data as i32;
-        let miss = write!(fd, "{value}");
-        match miss {
-            Err(_) => Err(Error::OutputError),
-            Ok(_) => Ok(()),
+        write!(fd, "{value}").map_err(Error::OutputError)
+    }
+    /// instruction in
+    fn input<R: Read>(&mut self, rd: u8, input: &mut R) -> Result<()> {
+        let value = match read_byte(input)? {
+            Some(byte) => u32::from(byte),
+            None => u32::MAX,
+        };
+        self.set_reg(rd as usize, value)
+    }
+    /// instruction in_number
+    fn input_number<R: Read>(&mut self, rd: u8, input: &mut R) -> Result<()> {
+        // skip blanks, then read an optional sign and digits; the character
+        // ending the number is consumed
+        let mut byte = read_byte(input)?;
+        while byte.is_some_and(|b| b.is_ascii_whitespace()) {
+            byte = read_byte(input)?;
+        }
+        let mut text = String::new();
+        if let Some(sign @ (b'-' | b'+')) = byte {
+            text.push(sign as char);
+            byte = read_byte(input)?;
         }
+        while let Some(digit) = byte.filter(u8::is_ascii_digit) {
+            text.push(digit as char);
+            byte = read_byte(input)?;
+        }
+        let value: i32 = text.parse().map_err(|_| {
+            let message = format!("`{text}` is not a valid number");
+            Error::InputError(io::Error::new(io::ErrorKind::InvalidData, message))
+        })?;
+        self.set_reg(rd as usize, value as u32)
     }
-    // verify that the instruction is not well placed in memory
-    fn is_last(&mut self, r0: usize) -> Result<()> {
-        if r0 >= MEMORY_SIZE {
-            self.set_reg(0, MEMORY_SIZE as u32)?;
-            return Err(Error::MemoryOverflow);
+}
+
+/// Read one byte from `input`, or `None` at the end of input.
+fn read_byte<R: Read>(input: &mut R) -> Result<Option<u8>> {
+    let mut byte = 0;
+    loop {
+        match input.read(std::slice::from_mut(&mut byte)) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte)),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(Error::InputError(e)),
         }
-        Ok(())
     }
 }
@@ -1,11 +1,455 @@
-fn main() -> Result<(), interpreter::Error> {
-    // Take a filename as argument on the command line
-    let filename = std::env::args().nth(1).unwrap();
+use clap::{Parser, Subcommand};
+use interpreter::{
+    Assembler, Coverage, FRAMEBUFFER_ADDRESS, FileFormat, Framebuffer,
+    INTERRUPT_CONTROLLER_ADDRESS, Limits, MEMORY_SIZE, Machine, Object, Outcome, Profile, Trace,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-    // Read content to buffer
-    let buffer = std::fs::read(filename).unwrap();
+/// Run or build programs for the virtual machine
+#[derive(Parser)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
 
-    // Create a machine with this memory content and run it
-    let mut machine = interpreter::Machine::new(&buffer).unwrap();
-    machine.run()
+    /// Binary program to run
+    #[clap(required_unless_present = "resume", conflicts_with = "resume")]
+    program: Option<PathBuf>,
+
+    /// Write a trace of every executed instruction to this file
+    #[clap(long)]
+    trace: Option<PathBuf>,
+
+    /// Stop the program after this number of instructions
+    #[clap(long)]
+    max_steps: Option<u64>,
+
+    /// Stop the program after this number of seconds
+    #[clap(long)]
+    timeout: Option<f64>,
+
+    /// Size of the machine memory in bytes
+    #[clap(long, default_value_t = MEMORY_SIZE)]
+    memory_size: usize,
+
+    /// Map an 8x8 RGB framebuffer at address 4096, and draw its frames on
+    /// the terminal or write them as PPM files in a directory
+    #[clap(long, value_name = "term|ppm:DIR", value_parser = parse_fb)]
+    fb: Option<FbOutput>,
+
+    /// Map the interrupt controller at address 4352
+    #[clap(long)]
+    interrupts: bool,
+
+    /// Print on the standard error how many times each address, instruction
+    /// kind, block and function was executed, using the labels of the
+    /// program listing (with a `.dis` extension) if any
+    #[clap(long)]
+    profile: bool,
+
+    /// Print on the standard error the program listing (with a `.dis`
+    /// extension) annotated with the number of executions of each
+    /// instruction
+    #[clap(long, conflicts_with = "resume")]
+    coverage: bool,
+
+    /// Write the coverage of the program listing to this file in the lcov
+    /// format
+    #[clap(long, value_name = "FILE", conflicts_with = "resume")]
+    lcov: Option<PathBuf>,
+
+    /// Save a snapshot of the machine to this file when the run ends
+    #[clap(long, value_name = "FILE")]
+    save_on_exit: Option<PathBuf>,
+
+    /// Resume the run saved in this snapshot instead of running a program,
+    /// with the same framebuffer and interrupt options
+    #[clap(long, value_name = "FILE")]
+    resume: Option<PathBuf>,
+}
+
+/// Destination of the framebuffer frames.
+#[derive(Clone)]
+enum FbOutput {
+    Term,
+    Ppm(PathBuf),
+}
+
+fn parse_fb(text: &str) -> Result<FbOutput, String> {
+    match text.split_once(':') {
+        None if text == "term" => Ok(FbOutput::Term),
+        Some(("ppm", dir)) if !dir.is_empty() => Ok(FbOutput::Ppm(dir.into())),
+        _ => Err("expected `term` or `ppm:DIR`".to_owned()),
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Assemble a listing into a binary program
+    Asm {
+        /// Listing to assemble, in the format of the `.dis` files
+        source: PathBuf,
+
+        /// Binary file to write (defaults to the source with a `.bin` extension)
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write an object file, with sections and symbols, rather than a
+        /// raw image
+        #[clap(long)]
+        object: bool,
+    },
+
+    /// Link object files, or listings, into a single object file
+    Link {
+        /// Object files and listings (with a `.dis` extension) to link
+        #[clap(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Object file to write
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// Do not add the routines of the standard library when needed
+        #[clap(long)]
+        no_stdlib: bool,
+    },
+
+    /// Print the listing of a binary program
+    Disasm {
+        /// Binary program to disassemble
+        program: PathBuf,
+
+        /// Size of the machine memory in bytes
+        #[clap(long, default_value_t = MEMORY_SIZE)]
+        memory_size: usize,
+    },
+
+    /// Check a binary program without running it, following its control
+    /// flow from address 0
+    Check {
+        /// Binary program to check
+        program: PathBuf,
+
+        /// Size of the machine memory in bytes
+        #[clap(long, default_value_t = MEMORY_SIZE)]
+        memory_size: usize,
+    },
+
+    /// Debug a binary program interactively
+    Debug {
+        /// Binary program to debug
+        program: PathBuf,
+
+        /// Listing providing the labels (defaults to the program with a `.dis` extension)
+        #[clap(short, long)]
+        listing: Option<PathBuf>,
+
+        /// Size of the machine memory in bytes
+        #[clap(long, default_value_t = MEMORY_SIZE)]
+        memory_size: usize,
+    },
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let result = match args.command {
+        Some(Command::Asm {
+            source,
+            output,
+            object,
+        }) => {
+            let output = output.unwrap_or_else(|| source.with_extension("bin"));
+            assemble(&source, &output, object)
+        }
+        Some(Command::Link {
+            inputs,
+            output,
+            no_stdlib,
+        }) => link(&inputs, &output, no_stdlib),
+        Some(Command::Disasm {
+            program,
+            memory_size,
+        }) => read_object_within(&program, memory_size).map(|object| {
+            print!("{}", interpreter::disassemble_object(&object));
+        }),
+        Some(Command::Check {
+            program,
+            memory_size,
+        }) => check(&program, memory_size),
+        Some(Command::Debug {
+            program,
+            listing,
+            memory_size,
+        }) => debug(&program, listing, memory_size),
+        None => run(&args),
+    };
+
+    // Report errors in a readable way rather than through their debug form
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("vm: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Read a file, describing the failure if any.
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Read a program file to run, either an object file or a raw image,
+/// rejecting files which look like neither.
+fn read_program(path: &Path) -> Result<Vec<u8>, String> {
+    let file = read(path)?;
+    match FileFormat::detect(&file) {
+        Some(_) => Ok(file),
+        None => Err(format!(
+            "{}: unrecognized file format, neither an object file nor a program image",
+            path.display()
+        )),
+    }
+}
+
+/// Read a program file as an object, raw images being turned into an object
+/// with a single section.
+fn read_object(path: &Path) -> Result<Object, String> {
+    let file = read(path)?;
+    if Object::is_object(&file) {
+        Object::decode(&file).map_err(|e| format!("{}: {e}", path.display()))
+    } else {
+        Ok(Object::from_image(&file))
+    }
+}
+
+/// Similar to [`read_object`], but check that the object fits in a memory
+/// of `memory_size` bytes, before anything builds its image.
+fn read_object_within(path: &Path, memory_size: usize) -> Result<Object, String> {
+    let object = read_object(path)?;
+    let size = object.size();
+    if size > memory_size {
+        let error = interpreter::Error::ProgramTooLarge { size, memory_size };
+        return Err(format!("{}: {error}", path.display()));
+    }
+    Ok(object)
+}
+
+/// Assembler for the listing found in `path`, whose includes are relative
+/// to its directory.
+fn assembler(path: &Path) -> Assembler {
+    Assembler::new().include_dir(path.parent().unwrap_or(Path::new("")))
+}
+
+/// Read and assemble a listing, describing the failure if any.
+fn read_listing(path: &Path) -> Result<(Vec<u8>, interpreter::Labels), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    (assembler(path).assemble_with_labels(&text)).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// Read the labels of `listing`, or of the listing of `program` if it
+/// exists, or else the symbols of `program` if it is an object file.
+fn labels(program: &Path, listing: Option<PathBuf>) -> Result<interpreter::Labels, String> {
+    // Labels are optional unless a listing is explicitly given
+    let listing = listing.or_else(|| {
+        let default = program.with_extension("dis");
+        default.exists().then_some(default)
+    });
+    match listing {
+        Some(listing) => Ok(read_listing(&listing)?.1),
+        None => Ok(read_object(program)?.symbols),
+    }
+}
+
+/// Create a machine loaded with `file`, the program found in `path`.
+fn load(path: &Path, file: &[u8], memory_size: usize) -> Result<Machine, String> {
+    Machine::builder()
+        .memory_size(memory_size)
+        .load(file)
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let (filename, mut machine) = match &args.resume {
+        Some(snapshot) => (snapshot.as_path(), Machine::new(&[]).unwrap()),
+        None => {
+            let program = args.program.as_deref().unwrap();
+            (
+                program,
+                load(program, &read_program(program)?, args.memory_size)?,
+            )
+        }
+    };
+    let timeout = args
+        .timeout
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|e| format!("invalid timeout: {e}"))?;
+    let limits = Limits {
+        max_steps: args.max_steps,
+        deadline: timeout.map(|timeout| Instant::now() + timeout),
+    };
+    if let Some(fb) = &args.fb {
+        let framebuffer = match fb {
+            FbOutput::Term => Framebuffer::terminal(std::io::stdout()),
+            FbOutput::Ppm(dir) => Framebuffer::ppm(dir),
+        };
+        machine
+            .map_device(FRAMEBUFFER_ADDRESS, Box::new(framebuffer))
+            .map_err(|e| format!("{}: {e}", filename.display()))?;
+    }
+    if args.interrupts {
+        machine
+            .map_interrupt_controller(INTERRUPT_CONTROLLER_ADDRESS)
+            .map_err(|e| format!("{}: {e}", filename.display()))?;
+    }
+    if let Some(snapshot) = &args.resume {
+        machine
+            .restore(&read(snapshot)?)
+            .map_err(|e| format!("{}: {e}", snapshot.display()))?;
+    }
+    let trace = args.trace.as_ref();
+    if let Some(trace) = trace {
+        let file = File::create(trace).map_err(|e| format!("{}: {e}", trace.display()))?;
+        machine.set_trace(Some(Trace::Writer(Box::new(BufWriter::new(file)))));
+    }
+    if args.profile || args.coverage || args.lcov.is_some() {
+        machine.set_profile(Some(Profile::new()));
+    }
+    let result = machine.run_limited(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        limits,
+    );
+    if let (Some(trace), Some(Trace::Writer(mut writer))) = (trace, machine.set_trace(None)) {
+        writer
+            .flush()
+            .map_err(|e| format!("{}: {e}", trace.display()))?;
+    }
+    if let Some(profile) = machine.set_profile(None) {
+        report(args, &profile)?;
+    }
+    if let Some(snapshot) = &args.save_on_exit {
+        std::fs::write(snapshot, machine.snapshot())
+            .map_err(|e| format!("{}: {e}", snapshot.display()))?;
+    }
+    match result.map_err(|e| format!("{}: {e}", filename.display()))? {
+        Outcome::Exited { .. } => Ok(()),
+        Outcome::BudgetExhausted { steps } => Err(format!(
+            "{}: stopped after executing {steps} instructions",
+            filename.display()
+        )),
+        Outcome::TimedOut { steps } => Err(format!(
+            "{}: timed out after executing {steps} instructions",
+            filename.display()
+        )),
+    }
+}
+
+/// Print or write the profile and coverage reports requested by `args`.
+fn report(args: &Args, profile: &Profile) -> Result<(), String> {
+    if args.profile {
+        let labels = match &args.program {
+            Some(program) => labels(program, None)?,
+            None => interpreter::Labels::new(),
+        };
+        eprint!("{}", profile.report(&labels));
+    }
+    if !args.coverage && args.lcov.is_none() {
+        return Ok(());
+    }
+    let listing = args.program.as_deref().unwrap().with_extension("dis");
+    let text =
+        std::fs::read_to_string(&listing).map_err(|e| format!("{}: {e}", listing.display()))?;
+    let coverage = Coverage::with_assembler(&text, &assembler(&listing), profile)
+        .map_err(|e| format!("{}: {e}", listing.display()))?;
+    if args.coverage {
+        eprint!("{}", coverage.annotate());
+    }
+    if let Some(lcov) = &args.lcov {
+        std::fs::write(lcov, coverage.lcov(&listing.display().to_string()))
+            .map_err(|e| format!("{}: {e}", lcov.display()))?;
+    }
+    Ok(())
+}
+
+fn assemble(source: &Path, output: &Path, object: bool) -> Result<(), String> {
+    let binary = if object {
+        let text =
+            std::fs::read_to_string(source).map_err(|e| format!("{}: {e}", source.display()))?;
+        assembler(source)
+            .assemble_object(&text)
+            .map_err(|e| format!("{}: {e}", source.display()))?
+            .encode()
+    } else {
+        read_listing(source)?.0
+    };
+    std::fs::write(output, binary).map_err(|e| format!("{}: {e}", output.display()))
+}
+
+fn link(inputs: &[PathBuf], output: &Path, no_stdlib: bool) -> Result<(), String> {
+    let mut objects = Vec::new();
+    for input in inputs {
+        objects.push(if input.extension().is_some_and(|ext| ext == "dis") {
+            let text =
+                std::fs::read_to_string(input).map_err(|e| format!("{}: {e}", input.display()))?;
+            (assembler(input).assemble_object(&text))
+                .map_err(|e| format!("{}: {e}", input.display()))?
+        } else {
+            // Raw images have no relocations, they cannot be moved
+            let file = read(input)?;
+            if !Object::is_object(&file) {
+                return Err(format!(
+                    "{}: not an object file, raw images cannot be linked",
+                    input.display()
+                ));
+            }
+            Object::decode(&file).map_err(|e| format!("{}: {e}", input.display()))?
+        });
+    }
+    let library = if no_stdlib {
+        Vec::new()
+    } else {
+        interpreter::stdlib()
+    };
+    let program = interpreter::link(&objects, &library).map_err(|e| e.to_string())?;
+    std::fs::write(output, program.encode()).map_err(|e| format!("{}: {e}", output.display()))
+}
+
+fn check(program: &Path, memory_size: usize) -> Result<(), String> {
+    let problems =
+        interpreter::check_object(&read_object_within(program, memory_size)?, memory_size);
+    for problem in &problems {
+        let severity = if problem.is_error() {
+            "error"
+        } else {
+            "warning"
+        };
+        println!("{}: {severity}: {problem}", program.display());
+    }
+    match problems.iter().filter(|problem| problem.is_error()).count() {
+        0 => Ok(()),
+        1 => Err(format!("{}: 1 error found", program.display())),
+        errors => Err(format!("{}: {errors} errors found", program.display())),
+    }
+}
+
+/// Number of instructions which can be undone in the debugger.
+const DEBUG_HISTORY: usize = 100_000;
+
+fn debug(program: &Path, listing: Option<PathBuf>, memory_size: usize) -> Result<(), String> {
+    // Programs under debugging may well start with an invalid instruction
+    let mut machine = load(program, &read(program)?, memory_size)?;
+    machine.set_history(DEBUG_HISTORY);
+
+    let mut debugger = interpreter::Debugger::new(machine, labels(program, listing)?);
+    debugger
+        .run(std::io::stdin().lock(), &mut std::io::stdout().lock())
+        .map_err(|e| e.to_string())
 }
@@ -0,0 +1,356 @@
+use crate::snapshot::{Decoder, Encoder};
+use crate::{Error, Instruction, Labels};
+use std::collections::BTreeSet;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// First bytes of every object file.
+const MAGIC: [u8; 4] = *b"VMOB";
+
+/// Version of the object file format, to be increased whenever it changes.
+/// Version 1 is the first published layout, nothing earlier was released.
+pub const OBJECT_VERSION: u32 = 1;
+
+/// Version of the instruction set, to be increased whenever existing
+/// programs may behave differently.
+pub const ISA_VERSION: u32 = 1;
+
+/// Content of a [`Section`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectionKind {
+    Code,
+    Data,
+}
+
+/// Bytes of a program to be loaded at `address`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub kind: SectionKind,
+    pub address: usize,
+    pub bytes: Vec<u8>,
+}
+
+impl Section {
+    /// Address following the section.
+    #[must_use]
+    pub fn end(&self) -> usize {
+        self.address + self.bytes.len()
+    }
+}
+
+/// Kind of field patched by a [`Relocation`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// Signed 16-bit immediate of a `loadimm`
+    LoadImm,
+    /// Unsigned 16-bit target of a `call`
+    Call,
+    /// Little-endian 32-bit word of data
+    Word,
+}
+
+/// Reference from the instruction or data word at `address` to a symbol,
+/// which may be defined by another object file. The field holds the address
+/// of the symbol plus `addend`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    pub address: usize,
+    pub kind: RelocationKind,
+    pub symbol: String,
+    pub addend: i32,
+}
+
+/// Program in the object file format, which unlike a raw image records
+/// where execution starts, where each section goes and the symbols defined
+/// by the program.
+///
+/// The file starts with the `VMOB` magic number followed by the format and
+/// ISA versions, the entry point, the sections (kind, address and bytes),
+/// the symbols (name and address), the relocations (kind, address, symbol
+/// and addend) and a CRC-32 of all that, every number being a little-endian
+/// u32.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+    /// Address of the first instruction to execute
+    pub entry: usize,
+    pub sections: Vec<Section>,
+    pub symbols: Labels,
+    /// References to symbols, needed to move the sections or to resolve
+    /// symbols defined elsewhere
+    pub relocations: Vec<Relocation>,
+}
+
+impl Object {
+    /// Object for a raw image, as a single code section at address 0.
+    #[must_use]
+    pub fn from_image(image: &[u8]) -> Self {
+        Object {
+            entry: 0,
+            sections: vec![Section {
+                kind: SectionKind::Code,
+                address: 0,
+                bytes: image.to_vec(),
+            }],
+            symbols: Labels::new(),
+            relocations: Vec::new(),
+        }
+    }
+
+    /// Whether `file` starts like an object file.
+    #[must_use]
+    pub fn is_object(file: &[u8]) -> bool {
+        file.starts_with(&MAGIC)
+    }
+
+    /// Encode the object in the object file format.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::plain();
+        encoder.raw(&MAGIC);
+        encoder.u32(OBJECT_VERSION);
+        encoder.u32(ISA_VERSION);
+        encoder.u32(self.entry as u32);
+        encoder.u32(self.sections.len() as u32);
+        for section in &self.sections {
+            encoder.u8(match section.kind {
+                SectionKind::Code => 0,
+                SectionKind::Data => 1,
+            });
+            encoder.u32(section.address as u32);
+            encoder.bytes(&section.bytes);
+        }
+        encoder.u32(self.symbols.len() as u32);
+        for (name, &address) in &self.symbols {
+            encoder.bytes(name.as_bytes());
+            encoder.u32(address as u32);
+        }
+        encoder.u32(self.relocations.len() as u32);
+        for relocation in &self.relocations {
+            encoder.u8(match relocation.kind {
+                RelocationKind::LoadImm => 0,
+                RelocationKind::Call => 1,
+                RelocationKind::Word => 2,
+            });
+            encoder.u32(relocation.address as u32);
+            encoder.bytes(relocation.symbol.as_bytes());
+            encoder.u32(relocation.addend as u32);
+        }
+        let mut file = encoder.finish();
+        file.extend(crc32(&file).to_le_bytes());
+        file
+    }
+
+    /// Decode an object file.
+    ///
+    /// # Errors
+    /// This function returns [`Error::InvalidObject`] if `file` is not an
+    /// object file, if it is corrupted, if it targets another version of
+    /// the format or of the instruction set, or if its sections overlap.
+    pub fn decode(file: &[u8]) -> Result<Self> {
+        if !Object::is_object(file) {
+            return Err(invalid("not an object file"));
+        }
+        let (content, checksum) = file.split_at(file.len().max(8) - 4);
+        if checksum != crc32(content).to_le_bytes() {
+            return Err(invalid("checksum mismatch"));
+        }
+        let mut decoder = Decoder::plain(&content[MAGIC.len()..], invalid);
+        let version = decoder.u32()?;
+        if version != OBJECT_VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        let isa = decoder.u32()?;
+        if isa != ISA_VERSION {
+            return Err(invalid(&format!("unsupported ISA version {isa}")));
+        }
+        let entry = decoder.u32()? as usize;
+        let mut sections: Vec<Section> = Vec::new();
+        for _ in 0..decoder.u32()? {
+            let kind = match decoder.u8()? {
+                0 => SectionKind::Code,
+                1 => SectionKind::Data,
+                kind => return Err(invalid(&format!("unknown section kind {kind}"))),
+            };
+            let section = Section {
+                kind,
+                address: decoder.u32()? as usize,
+                bytes: decoder.bytes()?.to_vec(),
+            };
+            if sections
+                .iter()
+                .any(|other| other.address < section.end() && section.address < other.end())
+            {
+                return Err(invalid(&format!(
+                    "overlapping sections at address {}",
+                    section.address
+                )));
+            }
+            sections.push(section);
+        }
+        let mut symbols = Labels::new();
+        for _ in 0..decoder.u32()? {
+            let name = symbol_name(decoder.bytes()?)?;
+            symbols.insert(name, decoder.u32()? as usize);
+        }
+        let mut relocations = Vec::new();
+        for _ in 0..decoder.u32()? {
+            let kind = match decoder.u8()? {
+                0 => RelocationKind::LoadImm,
+                1 => RelocationKind::Call,
+                2 => RelocationKind::Word,
+                kind => return Err(invalid(&format!("unknown relocation kind {kind}"))),
+            };
+            relocations.push(Relocation {
+                kind,
+                address: decoder.u32()? as usize,
+                symbol: symbol_name(decoder.bytes()?)?,
+                addend: decoder.u32()? as i32,
+            });
+        }
+        decoder.end()?;
+        Ok(Object {
+            entry,
+            sections,
+            symbols,
+            relocations,
+        })
+    }
+
+    /// Symbols referenced by relocations but not defined, which must be
+    /// provided by other object files.
+    #[must_use]
+    pub fn undefined(&self) -> BTreeSet<&str> {
+        (self.relocations.iter())
+            .map(|relocation| relocation.symbol.as_str())
+            .filter(|symbol| !self.symbols.contains_key(*symbol))
+            .collect()
+    }
+
+    /// Move the whole object `offset` bytes further in memory, without
+    /// patching the instructions referring to its symbols.
+    pub(crate) fn shift(&mut self, offset: usize) {
+        self.entry += offset;
+        for section in &mut self.sections {
+            section.address += offset;
+        }
+        for address in self.symbols.values_mut() {
+            *address += offset;
+        }
+        for relocation in &mut self.relocations {
+            relocation.address += offset;
+        }
+    }
+
+    /// Patch the field designated by `relocation` so that it holds `value`.
+    pub(crate) fn patch(&mut self, relocation: &Relocation, value: i64) -> Result<()> {
+        let overflow = || Error::RelocationOverflow {
+            symbol: relocation.symbol.clone(),
+            address: relocation.address,
+        };
+        let (offset, field) = match relocation.kind {
+            RelocationKind::LoadImm => {
+                let field = i16::try_from(value).map_err(|_| overflow())?;
+                (2, field.to_le_bytes().to_vec())
+            }
+            RelocationKind::Call => (
+                1,
+                u16::try_from(value)
+                    .map_err(|_| overflow())?
+                    .to_le_bytes()
+                    .to_vec(),
+            ),
+            RelocationKind::Word => (
+                0,
+                i32::try_from(value)
+                    .map_err(|_| overflow())?
+                    .to_le_bytes()
+                    .to_vec(),
+            ),
+        };
+        let address = relocation.address + offset;
+        let section = (self.sections.iter_mut())
+            .find(|section| section.address <= address && address + field.len() <= section.end())
+            .ok_or_else(overflow)?;
+        let start = address - section.address;
+        section.bytes[start..start + field.len()].copy_from_slice(&field);
+        Ok(())
+    }
+
+    /// Memory needed by the object, up to the end of its last section.
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.sections.iter().map(Section::end).max().unwrap_or(0)
+    }
+
+    /// Memory image from address 0 to the end of the last section, with
+    /// zeroes between sections.
+    #[must_use]
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = vec![0; self.size()];
+        for section in &self.sections {
+            image[section.address..section.end()].copy_from_slice(&section.bytes);
+        }
+        image
+    }
+}
+
+/// Format of a program file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Object file
+    Object,
+    /// Legacy memory image, copied as is at address 0
+    Raw,
+}
+
+impl FileFormat {
+    /// Recognize the format of `file`. Anything which is not an object file
+    /// is taken for a raw image, provided that its instructions decode one
+    /// after the other up to the first `exit` or the end of the file.
+    #[must_use]
+    pub fn detect(file: &[u8]) -> Option<Self> {
+        if Object::is_object(file) {
+            Some(FileFormat::Object)
+        } else if is_image(file) {
+            Some(FileFormat::Raw)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_image(file: &[u8]) -> bool {
+    let mut address = 0;
+    while address < file.len() {
+        match Instruction::decode(&file[address..]) {
+            Ok((Instruction::Exit, _)) => return true,
+            Ok((_, size)) => address += size,
+            Err(_) => return false,
+        }
+    }
+    address > 0
+}
+
+fn symbol_name(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid("symbol name is not valid UTF-8"))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidObject(reason.to_owned())
+}
+
+/// CRC-32 checksum, as used by zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,249 @@
+use crate::machine::{IP, NREGS, SP};
+use crate::{Instruction, Labels};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Number of addresses and blocks listed in the report.
+const HOTTEST: usize = 20;
+
+/// Executions of a basic block, i.e. of instructions run one after the
+/// other between two jumps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Block {
+    /// Number of times the block was entered
+    pub executions: u64,
+    /// Number of instructions executed in the block
+    pub instructions: u64,
+}
+
+/// Time spent in a function, i.e. the code reached by a call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Function {
+    pub calls: u64,
+    /// Instructions executed by the function itself
+    pub own: u64,
+    /// Instructions executed by the function and the functions it calls
+    pub total: u64,
+}
+
+// Function being executed
+struct Frame {
+    entry: usize,
+    return_address: Option<usize>,
+    start: u64,
+}
+
+/// Execution counts gathered by a [`Machine`](crate::Machine) while running.
+///
+/// A jump is considered as a call when the address following the jumping
+/// instruction is on the top of the stack (pointed to by r2), which is the
+/// case for `call` as well as for the `loadimm r0` of the compiled programs.
+/// Jumping back to this address returns from the call.
+#[derive(Default)]
+pub struct Profile {
+    instructions: u64,
+    addresses: Vec<(u64, Option<Instruction>)>,
+    kinds: BTreeMap<&'static str, u64>,
+    blocks: BTreeMap<(usize, usize), Block>,
+    // first and last addresses, and length of the block being executed
+    block: Option<(usize, usize, u64)>,
+    functions: BTreeMap<usize, Function>,
+    frames: Vec<Frame>,
+}
+
+impl Profile {
+    #[must_use]
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    /// Count an instruction which has been executed, given the registers
+    /// and memory once it is done.
+    pub(crate) fn record(
+        &mut self,
+        address: usize,
+        instruction: Instruction,
+        registers: &[u32; NREGS],
+        memory: &[u8],
+    ) {
+        if self.frames.is_empty() {
+            // The program itself is the outermost function
+            self.enter(address, None);
+        }
+        if self.addresses.len() <= address {
+            self.addresses.resize(address + 1, (0, None));
+        }
+        let (count, decoded) = &mut self.addresses[address];
+        *count += 1;
+        *decoded = Some(instruction);
+        *self.kinds.entry(instruction.mnemonic()).or_default() += 1;
+        let entry = self.frames.last().unwrap().entry;
+        self.functions.entry(entry).or_default().own += 1;
+        self.instructions += 1;
+        let (start, last, length) = self.block.get_or_insert((address, address, 0));
+        *last = address;
+        *length += 1;
+
+        let next = address + instruction.size();
+        let target = registers[IP] as usize;
+        if target == next {
+            return;
+        }
+        let (start, length) = (*start, *length);
+        self.block = None;
+        let block = self.blocks.entry((start, address)).or_default();
+        block.executions += 1;
+        block.instructions += length;
+        let sp = registers[SP] as usize;
+        let top = memory
+            .get(sp..sp + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()) as usize);
+        if let Some(depth) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_address == Some(target))
+        {
+            for _ in depth..self.frames.len() {
+                self.leave();
+            }
+        } else if top == Some(next) {
+            self.enter(target, Some(next));
+        }
+    }
+
+    fn enter(&mut self, entry: usize, return_address: Option<usize>) {
+        self.functions.entry(entry).or_default().calls += 1;
+        self.frames.push(Frame {
+            entry,
+            return_address,
+            start: self.instructions,
+        });
+    }
+
+    fn leave(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        // Recursive calls are already counted by the outermost one
+        if self.frames.iter().all(|outer| outer.entry != frame.entry) {
+            self.functions.entry(frame.entry).or_default().total += self.instructions - frame.start;
+        }
+    }
+
+    /// Number of instructions executed.
+    #[must_use]
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Number of executions of the instruction at `address`.
+    #[must_use]
+    pub fn count(&self, address: usize) -> u64 {
+        self.addresses.get(address).map_or(0, |&(count, _)| count)
+    }
+
+    /// Number of executed instructions of each kind, by mnemonic.
+    #[must_use]
+    pub fn kinds(&self) -> &BTreeMap<&'static str, u64> {
+        &self.kinds
+    }
+
+    /// Basic blocks executed, by first and last address, including the one
+    /// being executed.
+    #[must_use]
+    pub fn blocks(&self) -> BTreeMap<(usize, usize), Block> {
+        let mut blocks = self.blocks.clone();
+        if let Some((start, last, length)) = self.block {
+            let block = blocks.entry((start, last)).or_default();
+            block.executions += 1;
+            block.instructions += length;
+        }
+        blocks
+    }
+
+    /// Functions called, by entry address, including the ones still running.
+    /// The program itself counts as a function called once.
+    #[must_use]
+    pub fn functions(&self) -> BTreeMap<usize, Function> {
+        let mut functions = self.functions.clone();
+        for (depth, frame) in self.frames.iter().enumerate() {
+            if self.frames[..depth]
+                .iter()
+                .all(|outer| outer.entry != frame.entry)
+            {
+                functions.entry(frame.entry).or_default().total += self.instructions - frame.start;
+            }
+        }
+        functions
+    }
+
+    /// Report the counts sorted from the most to the least frequent, naming
+    /// addresses after the `labels` which precede them.
+    #[must_use]
+    pub fn report(&self, labels: &Labels) -> String {
+        let mut names = BTreeMap::new();
+        for (label, &address) in labels {
+            names.entry(address).or_insert(label.as_str());
+        }
+        let describe = |address: usize| match names.range(..=address).next_back() {
+            Some((&start, label)) if start == address => format!("{address:04} <{label}>"),
+            Some((&start, label)) => format!("{address:04} <{label}+{}>", address - start),
+            None => format!("{address:04}"),
+        };
+        let percent = |count: u64| 100.0 * count as f64 / self.instructions.max(1) as f64;
+
+        let mut text = format!("{} instructions executed\n", self.instructions);
+        text += "\ninstructions by kind:\n      count       %  kind\n";
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|&(kind, count)| (std::cmp::Reverse(*count), *kind));
+        for (kind, &count) in kinds {
+            writeln!(text, "{count:>11} {:>6.2}%  {kind}", percent(count)).unwrap();
+        }
+
+        text += "\nhottest addresses:\n      count       %  address\n";
+        let mut addresses: Vec<_> = (self.addresses.iter().enumerate())
+            .filter_map(|(address, &(count, instruction))| Some((address, count, instruction?)))
+            .collect();
+        addresses.sort_by_key(|&(address, count, _)| (std::cmp::Reverse(count), address));
+        for (address, count, instruction) in addresses.into_iter().take(HOTTEST) {
+            let percent = percent(count);
+            let address = describe(address);
+            writeln!(
+                text,
+                "{count:>11} {percent:>6.2}%  {address}   {instruction}"
+            )
+            .unwrap();
+        }
+
+        text += "\nhottest blocks:\n instructions       %  executions  block\n";
+        let mut blocks: Vec<_> = self.blocks().into_iter().collect();
+        blocks.sort_by_key(|&(range, block)| (std::cmp::Reverse(block.instructions), range));
+        for ((start, last), block) in blocks.into_iter().take(HOTTEST) {
+            writeln!(
+                text,
+                "{:>13} {:>6.2}% {:>11}  {} - {last:04}",
+                block.instructions,
+                percent(block.instructions),
+                block.executions,
+                describe(start)
+            )
+            .unwrap();
+        }
+
+        text += "\nfunctions:\n      total       %         own       %   calls  function\n";
+        let mut functions: Vec<_> = self.functions().into_iter().collect();
+        functions.sort_by_key(|&(entry, function)| (std::cmp::Reverse(function.total), entry));
+        for (entry, function) in functions {
+            writeln!(
+                text,
+                "{:>11} {:>6.2}% {:>11} {:>6.2}% {:>7}  {}",
+                function.total,
+                percent(function.total),
+                function.own,
+                percent(function.own),
+                function.calls,
+                describe(entry)
+            )
+            .unwrap();
+        }
+        text
+    }
+}
@@ -0,0 +1,126 @@
+use crate::Error;
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// First bytes of every snapshot.
+const MAGIC: [u8; 4] = *b"VMSS";
+
+/// Version of the snapshot format, to be increased whenever it changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Writer of the little-endian fields of a snapshot, or of other binary
+/// formats.
+pub(crate) struct Encoder(Vec<u8>);
+
+impl Encoder {
+    /// Start a snapshot by writing its header.
+    pub(crate) fn new() -> Self {
+        let mut encoder = Encoder::plain();
+        encoder.raw(&MAGIC);
+        encoder.u32(SNAPSHOT_VERSION);
+        encoder
+    }
+
+    /// Start writing fields without any header.
+    pub(crate) fn plain() -> Self {
+        Encoder(Vec::new())
+    }
+
+    /// Write `bytes` as they are.
+    pub(crate) fn raw(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    /// Write `bytes` preceded by their length.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// Reader of the fields written by an [`Encoder`].
+pub(crate) struct Decoder<'a> {
+    rest: &'a [u8],
+    // error describing malformed data
+    invalid: fn(&str) -> Error,
+}
+
+impl<'a> Decoder<'a> {
+    /// Check the header of `snapshot` and start reading its fields.
+    pub(crate) fn new(snapshot: &'a [u8]) -> Result<Self> {
+        let mut decoder = Decoder::plain(snapshot, invalid);
+        if decoder.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(invalid("not a snapshot"));
+        }
+        let version = decoder.u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        Ok(decoder)
+    }
+
+    /// Start reading fields without any header, reporting malformed data
+    /// with `invalid`.
+    pub(crate) fn plain(data: &'a [u8], invalid: fn(&str) -> Error) -> Self {
+        Decoder {
+            rest: data,
+            invalid,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.rest.len() < len {
+            return Err((self.invalid)("truncated"));
+        }
+        let (head, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Check that every field was read.
+    pub(crate) fn end(&self) -> Result<()> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err((self.invalid)("trailing bytes"))
+        }
+    }
+}
+
+/// Error describing why a snapshot cannot be restored.
+pub(crate) fn invalid(reason: &str) -> Error {
+    Error::InvalidSnapshot(reason.to_owned())
+}
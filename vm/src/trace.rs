@@ -0,0 +1,49 @@
+use crate::Instruction;
+use std::fmt;
+use std::io::Write;
+
+/// Effects of one executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Address of the instruction
+    pub address: usize,
+    pub instruction: Instruction,
+    /// Registers whose value changed, with their new value. The IP is only
+    /// listed when the instruction jumps elsewhere than the next instruction.
+    pub registers: Vec<(usize, u32)>,
+    /// Memory bytes written, with their address
+    pub memory: Vec<(usize, u8)>,
+}
+
+/// Format the record as one line, e.g.
+/// `0008   sub r2 <- r2 - r3 ; r2 = 0x00000ffc`.
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}   {}", self.address, self.instruction)?;
+        if self.registers.is_empty() && self.memory.is_empty() {
+            return Ok(());
+        }
+        write!(f, " ;")?;
+        for (reg, value) in &self.registers {
+            write!(f, " r{reg} = 0x{value:08x}")?;
+        }
+        // Group contiguous bytes together
+        let mut next = None;
+        for &(address, byte) in &self.memory {
+            if next != Some(address) {
+                write!(f, " [{address}] =")?;
+            }
+            write!(f, " {byte:02x}")?;
+            next = Some(address + 1);
+        }
+        Ok(())
+    }
+}
+
+/// Destination of the execution trace of a [`Machine`](crate::Machine).
+pub enum Trace {
+    /// Keep the records in memory
+    Records(Vec<TraceRecord>),
+    /// Write each record as a line of text
+    Writer(Box<dyn Write>),
+}
@@ -0,0 +1,422 @@
+use interpreter::{
+    Assembler, Machine, SectionKind, assemble, assemble_object, assemble_with_labels,
+};
+
+// Assemble a listing and compare the result with the binary shipped next to it
+fn check(path: &str) {
+    let source = std::fs::read_to_string(format!("{path}.dis")).unwrap();
+    let expected = std::fs::read(format!("{path}.bin")).unwrap();
+    assert_eq!(expected, assemble(&source).unwrap(), "{path}");
+}
+
+#[test]
+fn assemble_examples() {
+    for name in [
+        "99bottles",
+        "count",
+        "factorial",
+        "fibonacci",
+        "gradient",
+        "hello_world",
+    ] {
+        check(&format!("examples/{name}"));
+    }
+}
+
+#[test]
+fn assemble_tests() {
+    for name in [
+        "afact", "fact", "fibo", "function", "multiply", "push_pop", "rfact", "rfact_tr",
+    ] {
+        check(&format!("tests/{name}"));
+    }
+}
+
+#[test]
+fn encode_each_instruction() {
+    let source = "
+        move r1 <- r2 if r3 != 0
+        store [r2] <- r3
+        load r1 <- [r2]
+        loadimm r1 <- #-2
+        sub r10 <- r2 - r1
+        out r5
+        out_number r5
+        exit
+        in r6
+        in_number r7
+    ";
+    assert_eq!(
+        vec![
+            1, 1, 2, 3, 2, 2, 3, 3, 1, 2, 4, 1, 0xfe, 0xff, 5, 10, 2, 1, 6, 5, 8, 5, 7, 9, 6, 10, 7
+        ],
+        assemble(source).unwrap()
+    );
+}
+
+#[test]
+fn encode_arithmetic_and_logic() {
+    let source = "
+        add r1 <- r2 + r3
+        mul r1 <- r2 * r3
+        div r1 <- r2 / r3
+        divu r1 <- r2 / r3
+        rem r1 <- r2 % r3
+        remu r1 <- r2 % r3
+        and r1 <- r2 & r3
+        or r1 <- r2 | r3
+        xor r1 <- r2 ^ r3
+        not r1 <- ~r2
+        shl r1 <- r2 << r3
+        shr r1 <- r2 >> r3
+        sar r1 <- r2 >> r3
+    ";
+    let mut expected = Vec::new();
+    for opcode in 11..=23 {
+        if opcode == 20 {
+            expected.extend([20, 1, 2]);
+        } else {
+            expected.extend([opcode, 1, 2, 3]);
+        }
+    }
+    assert_eq!(expected, assemble(source).unwrap());
+    // The operator must match the mnemonic
+    assert!(assemble("add r1 <- r2 - r3").is_err());
+}
+
+#[test]
+fn encode_stack_instructions() {
+    let source = "
+        push r3
+        pop r4
+        call #function
+        ret
+      function:
+        call #40000
+    ";
+    assert_eq!(
+        vec![24, 3, 25, 4, 26, 8, 0, 27, 26, 0x40, 0x9c],
+        assemble(source).unwrap()
+    );
+    assert!(assemble("call #-1").is_err());
+    assert!(assemble("call #65536").is_err());
+}
+
+#[test]
+fn encode_comparisons_and_branches() {
+    let source = "
+      top:
+        slt r1 <- r2 < r3
+        sltu r1 <- r2 < r3
+        seq r1 <- r2 == r3
+        beq r1, r2, #top
+        bne r1, r2, #end
+        blt r1, r2, #-2
+        bge r1, r2, #0
+      end:
+    ";
+    assert_eq!(
+        vec![
+            28, 1, 2, 3, 29, 1, 2, 3, 30, 1, 2, 3, 31, 1, 2, 0xf4, 0xff, 32, 1, 2, 15, 0, 33, 1, 2,
+            0xfe, 0xff, 34, 1, 2, 0, 0
+        ],
+        assemble(source).unwrap()
+    );
+    assert!(assemble("beq r1 r2 #0").is_err());
+    assert!(assemble("beq r1, r2, #32768").is_err());
+}
+
+#[test]
+fn labels_and_data() {
+    let source = "
+        start: loadimm r0 <- #end   ; forward reference
+        data:
+          ???? b'A\\'\\n\\x00'
+          ???? [1, 2]
+        end:
+          ???? b\"I'm\"
+    ";
+    let image = assemble(source).unwrap();
+    assert_eq!(&[4, 0, 10, 0, b'A', b'\'', b'\n', 0, 1, 2], &image[..10]);
+    assert_eq!(b"I'm", &image[10..]);
+}
+
+#[test]
+fn report_errors_with_line() {
+    let error = assemble("exit\nloadimm r16 <- #0").unwrap_err();
+    assert_eq!(2, error.line);
+    assert!(assemble("loadimm r0 <- #nowhere").is_err());
+    assert!(assemble("a:\na:").is_err());
+    assert!(assemble("loadimm r1 <- #32768").is_err());
+    assert!(assemble("jump r1").is_err());
+    assert!(assemble("exit r1").is_err());
+    assert!(assemble("b'unterminated").is_err());
+}
+
+#[test]
+fn expressions_and_constants() {
+    let source = "
+        .equ SIZE, 4
+        .equ LAST, SIZE - 1
+        loadimm r3 <- #SIZE
+        loadimm r3 <- #LAST
+        loadimm r10 <- #message+1
+        loadimm r11 <- #end-message
+        loadimm r12 <- #-SIZE+2
+        beq r1, r2, #SIZE+1
+        exit
+    message:
+        b'hello'
+    end:
+    ";
+    let expected = "
+        loadimm r3 <- #4
+        loadimm r3 <- #3
+        loadimm r10 <- #27
+        loadimm r11 <- #5
+        loadimm r12 <- #-2
+        beq r1, r2, #5
+        exit
+        b'hello'
+    ";
+    assert_eq!(assemble(expected).unwrap(), assemble(source).unwrap());
+    assert!(assemble(".equ A, label\nlabel:").is_err());
+    assert!(assemble(".equ A, 1\n.equ A, 2").is_err());
+    assert!(assemble(".equ A, 1\nA:").is_err());
+    assert!(assemble(".frobnicate").is_err());
+}
+
+#[test]
+fn macros() {
+    let source = "
+        .macro push_reg reg
+          loadimm r3 <- #4
+          sub r2 <- r2 - r3
+          store [r2] <- \\reg
+        .endm
+        .macro skip_if_zero reg, count
+          beq \\reg, r0, #skip\\@
+          loadimm r3 <- #\\count
+          sub \\reg <- \\reg - r3
+        skip\\@:
+        .endm
+        push_reg r10
+        push_reg r11 ; second register
+        skip_if_zero r1, 1
+        skip_if_zero r4, 2
+        exit
+    ";
+    let expected = "
+        loadimm r3 <- #4
+        sub r2 <- r2 - r3
+        store [r2] <- r10
+        loadimm r3 <- #4
+        sub r2 <- r2 - r3
+        store [r2] <- r11
+        beq r1, r0, #skip0
+        loadimm r3 <- #1
+        sub r1 <- r1 - r3
+    skip0:
+        beq r4, r0, #skip1
+        loadimm r3 <- #2
+        sub r4 <- r4 - r3
+    skip1:
+        exit
+    ";
+    assert_eq!(assemble(expected).unwrap(), assemble(source).unwrap());
+
+    let error = assemble(".macro m a\nexit\n.endm\nm").unwrap_err();
+    assert_eq!(
+        "line 4: macro `m` expects 1 arguments, found 0",
+        error.to_string()
+    );
+    let error = assemble(".macro m\nexit\nbad\n.endm\nexit\nm").unwrap_err();
+    assert_eq!(
+        "line 6: in macro `m`, line 2: unknown instruction `bad`",
+        error.to_string()
+    );
+    assert!(assemble(".macro m\nexit").is_err());
+    let error = assemble(".macro m\nm\n.endm\nexit\nm").unwrap_err();
+    assert_eq!(
+        "line 5: too many nested includes or macros",
+        error.to_string()
+    );
+}
+
+#[test]
+fn include_listings() {
+    let path = std::env::temp_dir().join("vm-assembler-include.dis");
+    std::fs::write(&path, "message:\n  b'hi'\n").unwrap();
+    let source = format!(
+        "loadimm r10 <- #message\nexit\n.include \"{}\"",
+        path.display()
+    );
+    assert_eq!(
+        assemble("loadimm r10 <- #5\nexit\nb'hi'").unwrap(),
+        assemble(&source).unwrap()
+    );
+
+    std::fs::write(&path, "exit\nexit r1\n").unwrap();
+    let error = assemble(&source).unwrap_err();
+    assert_eq!(3, error.line);
+    assert!(error.message.ends_with("line 2: unexpected `r1`"));
+    assert!(assemble(".include \"/nonexistent.dis\"").is_err());
+}
+
+#[test]
+fn include_relative_to_listing() {
+    let dir = std::env::temp_dir().join("vm-assembler-includes");
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/routines.dis"), ".include \"data.dis\"\n").unwrap();
+    std::fs::write(dir.join("lib/data.dis"), "message:\n  b'hi'\n").unwrap();
+    let source = "loadimm r10 <- #message\nexit\n.include \"lib/routines.dis\"";
+    assert_eq!(
+        assemble("loadimm r10 <- #5\nexit\nb'hi'").unwrap(),
+        Assembler::new().include_dir(&dir).assemble(source).unwrap()
+    );
+    assert!(assemble(source).is_err());
+}
+
+#[test]
+fn load_constants() {
+    for value in [
+        0,
+        -1,
+        0x7fff,
+        0x8000,
+        0x1_0000,
+        0x1234_5678,
+        0x1234_8000,
+        0xffff_ffff_i64,
+    ] {
+        for rd in [1, 3] {
+            let source = format!("li r{rd}, {value}\nexit");
+            let mut machine = Machine::new(&assemble(&source).unwrap()).unwrap();
+            machine.run().unwrap();
+            assert_eq!(value as u32, machine.regs()[rd], "{source}");
+        }
+    }
+    assert_eq!(
+        assemble("loadimm r3 <- #-2").unwrap(),
+        assemble("li r3, #-2").unwrap()
+    );
+    assert_eq!(4 * 5, assemble("li r3, 0x12345678").unwrap().len());
+    assert_eq!(4 * 3, assemble("li r3, 0x10000").unwrap().len());
+    assert!(assemble("li r3, 0x100000000").is_err());
+    assert!(assemble("li r3, #-0x80000001").is_err());
+}
+
+#[test]
+fn relocate_expressions() {
+    let object = assemble_object(
+        "loadimm r10 <- #message+4\nloadimm r11 <- #end-message\nexit\nmessage:\nend:",
+    )
+    .unwrap();
+    assert_eq!(1, object.relocations.len());
+    assert_eq!(4, object.relocations[0].addend);
+    let object = assemble_object("loadimm r10 <- #external+2\nexit").unwrap();
+    assert_eq!("external", object.relocations[0].symbol);
+    assert_eq!(2, object.relocations[0].addend);
+    assert!(assemble_object("loadimm r10 <- #-external\nexit").is_err());
+}
+
+#[test]
+fn data_directives() {
+    let source = "
+        .equ SIZE, 4
+        exit
+    bytes:
+        .byte 1, 0xff, -1, SIZE
+        .word 0x12345678, -2, bytes
+        .string \"hi\\n\"
+        .asciz \"a\" ; same as .string
+        .space 3
+    ";
+    let mut expected = vec![7, 1, 0xff, 0xff, 4];
+    expected.extend([0x78, 0x56, 0x34, 0x12, 0xfe, 0xff, 0xff, 0xff, 1, 0, 0, 0]);
+    expected.extend(b"hi\n\0a\0\0\0\0");
+    assert_eq!(expected, assemble(source).unwrap());
+
+    // Words are read back by `load`
+    let source = "
+        loadimm r10 <- #value
+        load r1 <- [r10]
+        out_number r1
+        exit
+    value:
+        .word 123456
+    ";
+    let mut machine = Machine::new(&assemble(source).unwrap()).unwrap();
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"123456", &output[..]);
+
+    assert!(assemble(".byte 256").is_err());
+    assert!(assemble(".byte label\nlabel:").is_err());
+    assert!(assemble(".word 0x100000000").is_err());
+    assert!(assemble(".string 'hi'").is_err());
+    assert!(assemble(".space -1").is_err());
+}
+
+#[test]
+fn align_and_org() {
+    let source = "
+        exit
+        .align 4
+    first:
+        .byte 1
+        .org 16
+    second:
+        .word 2
+        .org 8
+    third:
+        exit
+    ";
+    let (image, labels) = assemble_with_labels(source).unwrap();
+    assert_eq!(
+        vec![7, 0, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0],
+        image
+    );
+    assert_eq!(
+        [4, 16, 8],
+        [labels["first"], labels["second"], labels["third"]]
+    );
+    let object = assemble_object(source).unwrap();
+    let sections: Vec<_> = (object.sections.iter())
+        .map(|section| (section.kind, section.address, section.bytes.len()))
+        .collect();
+    assert_eq!(
+        vec![
+            (SectionKind::Code, 0, 1),
+            (SectionKind::Data, 1, 4),
+            (SectionKind::Data, 16, 4),
+            (SectionKind::Code, 8, 1)
+        ],
+        sections
+    );
+    assert!(assemble(".align 3").is_err());
+}
+
+#[test]
+fn report_layout_errors() {
+    let error = assemble(".org 4\nexit\n.org 0\n.space 5").unwrap_err();
+    assert_eq!(
+        "line 4: address 4 is already taken by line 2",
+        error.to_string()
+    );
+    let error = assemble("exit\n.org 4095\n.word 0").unwrap_err();
+    assert_eq!(
+        "line 3: image of 4099 bytes does not fit in memory (4096 bytes)",
+        error.to_string()
+    );
+    assert!(assemble(".org 4095\n.byte 0").is_ok());
+    // Sizes are checked before anything gets allocated
+    let error = assemble("exit\n.space 100000000000000").unwrap_err();
+    assert_eq!(
+        "line 2: image of 100000000000001 bytes does not fit in memory (4096 bytes)",
+        error.to_string()
+    );
+    assert!(assemble("exit\n.align 0x4000000000000000").is_err());
+    assert!(assemble(".org 0x7fffffffffffffff\n.space 0x7fffffffffffffff").is_err());
+    assert!(assemble(".org -1").is_err());
+}
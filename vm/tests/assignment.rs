@@ -27,7 +27,7 @@ fn test_assignment() {
 
     // load
     let mut mem = vec![3, 1, 2];
-    mem.extend(std::iter::repeat(0).take(22));
+    mem.extend(std::iter::repeat_n(0, 22));
     mem.extend(&[0xcd, 0xab, 0x34, 0x12]);
     let (m, _) = create_machine(&mem);
     assert_eq!(0x1234_abcd, m.regs()[1]);
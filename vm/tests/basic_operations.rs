@@ -146,7 +146,7 @@ fn test_store() {
     let mut machine = Machine::new(&[2, 0, 1]).unwrap();
     machine.set_reg(1, 0x0102_0304).unwrap();
     expect(&mut machine, false, 3);
-    assert_eq!(&[04, 03, 02, 01], &machine.memory()[3..7]);
+    assert_eq!(&[4, 3, 2, 1], &machine.memory()[3..7]);
 }
 
 #[test]
@@ -218,6 +218,125 @@ fn test_sub_out_of_bounds() {
     assert!(machine.step().is_err());
 }
 
+#[test]
+fn test_binary() {
+    let cases: [(u8, u32, u32, u32); 27] = [
+        // add, mul
+        (11, 40, 2, 42),
+        (11, u32::MAX, 2, 1),
+        (12, 6, 7, 42),
+        (12, -3i32 as u32, 5, -15i32 as u32),
+        // div, divu
+        (13, -7i32 as u32, 2, -3i32 as u32),
+        (13, i32::MIN as u32, -1i32 as u32, i32::MIN as u32),
+        (14, -7i32 as u32, 2, 0x7fff_fffc),
+        // rem, remu
+        (15, -7i32 as u32, 2, -1i32 as u32),
+        (15, i32::MIN as u32, -1i32 as u32, 0),
+        (16, -7i32 as u32, 2, 1),
+        // and, or, xor
+        (17, 0b1100, 0b1010, 0b1000),
+        (18, 0b1100, 0b1010, 0b1110),
+        (19, 0b1100, 0b1010, 0b0110),
+        // shl, shr, sar
+        (21, 1, 4, 16),
+        (21, 1, 33, 2),
+        (22, 0x8000_0000, 4, 0x0800_0000),
+        (22, 16, 36, 1),
+        (23, 0x8000_0000, 4, 0xf800_0000),
+        (23, 16, 2, 4),
+        (23, -1i32 as u32, 31, -1i32 as u32),
+        // slt, sltu, seq
+        (28, -1i32 as u32, 1, 1),
+        (28, 1, -1i32 as u32, 0),
+        (28, 3, 3, 0),
+        (29, -1i32 as u32, 1, 0),
+        (29, 1, -1i32 as u32, 1),
+        (30, 3, 3, 1),
+        (30, 3, 4, 0),
+    ];
+    for (opcode, a, b, result) in cases {
+        // 0: <op> r1 <- r2 <symbol> r3
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 2, 3]).unwrap();
+        machine.set_reg(2, a).unwrap();
+        machine.set_reg(3, b).unwrap();
+        expect(&mut machine, false, 4);
+        assert_eq!(result, machine.regs()[1], "opcode {opcode}: {a} and {b}");
+    }
+}
+
+#[test]
+fn test_binary_division_by_zero() {
+    for opcode in 13..=16 {
+        // 0: <op> r1 <- r2 <symbol> r3
+        // 4:
+        let mut machine = Machine::new(&[opcode, 1, 2, 3]).unwrap();
+        machine.set_reg(2, 42).unwrap();
+        assert!(machine.step().is_err());
+    }
+}
+
+#[test]
+fn test_binary_out_of_bounds() {
+    // 0: add r100 <- r0 + r0
+    // 4:
+    let mut machine = Machine::new(&[11, 100, 0, 0]).unwrap();
+    assert!(machine.step().is_err());
+
+    // 0: add r0 <- r0 + r100
+    // 4:
+    let mut machine = Machine::new(&[11, 0, 0, 100]).unwrap();
+    assert!(machine.step().is_err());
+}
+
+#[test]
+fn test_branch() {
+    // (opcode, rs1, rs2, taken)
+    let cases = [
+        (31, 3, 3, true),
+        (31, 3, 4, false),
+        (32, 3, 4, true),
+        (32, 3, 3, false),
+        (33, -1i32 as u32, 1, true),
+        (33, 1, 1, false),
+        (34, 1, 1, true),
+        (34, -1i32 as u32, 1, false),
+    ];
+    for (opcode, a, b, taken) in cases {
+        // 0: exit
+        // 1: <cond> r1, r2, #-1
+        // 6:
+        let mut machine = Machine::new(&[7, opcode, 1, 2, 0xff, 0xff]).unwrap();
+        machine.set_reg(0, 1).unwrap();
+        machine.set_reg(1, a).unwrap();
+        machine.set_reg(2, b).unwrap();
+        // The offset is relative to the address of the branch
+        expect(&mut machine, false, if taken { 0 } else { 6 });
+    }
+}
+
+#[test]
+fn test_branch_forward() {
+    // 0: bne r1, r0, #7
+    // 5: out_number r1
+    // 7: exit
+    // 8:
+    let mut machine = Machine::new(&[32, 1, 0, 7, 0, 8, 1, 7]).unwrap();
+    expect(&mut machine, false, 7);
+    expect(&mut machine, true, 8);
+}
+
+#[test]
+fn test_not() {
+    // 0: not r1 <- ~r2
+    // 3:
+    let mut machine = Machine::new(&[20, 1, 2]).unwrap();
+    machine.set_reg(2, 0x0f0f_0000).unwrap();
+    expect(&mut machine, false, 3);
+    assert_eq!(0xf0f0_ffff, machine.regs()[1]);
+}
+
 #[test]
 fn test_out() {
     // 0: out r1
@@ -288,9 +407,7 @@ fn no_wraparound_past_end_of_memory() {
     // 0:             exit
     // 1:
     let mut memory = [0; MEMORY_SIZE];
-    for i in MEMORY_SIZE - 4..MEMORY_SIZE {
-        memory[i] = 1;
-    }
+    memory[MEMORY_SIZE - 4..].fill(1);
     memory[0] = 7;
     let mut machine = Machine::new(&memory).unwrap();
     machine.set_reg(0, (MEMORY_SIZE - 4) as u32).unwrap();
@@ -388,9 +505,97 @@ fn test_invalid_opcode() {
     // 4: exit
     // 5:
     let mut memory = [0, 7, 7, 7, 7];
-    for invalid in std::iter::once(0).chain(9..u8::MAX) {
+    for invalid in std::iter::once(0).chain(36..u8::MAX) {
         memory[0] = invalid;
         let mut machine = Machine::new(&memory).unwrap();
         assert!(machine.step().is_err());
     }
 }
+
+#[test]
+fn test_in() {
+    // 0: in r1
+    // 2: in r2
+    // 4: in r3
+    // 6:
+    let mut machine = Machine::new(&[9, 1, 9, 2, 9, 3]).unwrap();
+    let mut input = "A\n".as_bytes();
+    for ip in [2, 4, 6] {
+        assert!(!machine.step_with(&mut input, &mut Vec::new()).unwrap());
+        assert_eq!(ip, machine.regs()[0]);
+    }
+    assert_eq!(
+        &[u32::from(b'A'), u32::from(b'\n'), 0xffff_ffff],
+        &machine.regs()[1..4]
+    );
+
+    // Without any input, the end of input is found at once
+    let mut machine = Machine::new(&[9, 1]).unwrap();
+    expect_on(&mut machine, &mut Vec::new(), false, 2);
+    assert_eq!(-1, machine.regs()[1] as i32);
+}
+
+#[test]
+fn test_in_number() {
+    // 0: in_number r1
+    // 2: in_number r2
+    // 4: in r3
+    // 6:
+    let mut machine = Machine::new(&[10, 1, 10, 2, 9, 3]).unwrap();
+    let mut input = "  42\n-1234 x".as_bytes();
+    machine.step_with(&mut input, &mut Vec::new()).unwrap();
+    machine.step_with(&mut input, &mut Vec::new()).unwrap();
+    machine.step_with(&mut input, &mut Vec::new()).unwrap();
+    assert_eq!(42, machine.regs()[1]);
+    assert_eq!(-1234, machine.regs()[2] as i32);
+    // The character ending the number has been consumed
+    assert_eq!(u32::from(b'x'), machine.regs()[3]);
+}
+
+#[test]
+fn test_in_number_invalid() {
+    // 0: in_number r1
+    // 2:
+    for input in ["", "x", "-", "99999999999"] {
+        let mut machine = Machine::new(&[10, 1]).unwrap();
+        assert!(
+            machine
+                .step_with(&mut input.as_bytes(), &mut Vec::new())
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn test_run_with() {
+    // 0: in_number r1
+    // 2: out_number r1
+    // 4: in r1
+    // 6: out r1
+    // 8: exit
+    // 9:
+    let mut machine = Machine::new(&[10, 1, 8, 1, 9, 1, 6, 1, 7]).unwrap();
+    let mut out = Vec::new();
+    machine.run_with(&mut "-7 !".as_bytes(), &mut out).unwrap();
+    assert_eq!("-7!".as_bytes(), &out[..]);
+}
+
+#[test]
+fn test_self_modifying_code() {
+    // 0: loadimm r1 <- #65
+    // 4: out r1
+    // 6: loadimm r3 <- #4
+    // 10: loadimm r5 <- #24
+    // 14: load r4 <- [r5]
+    // 17: store [r3] <- r4
+    // 20: loadimm r0 <- #4
+    // 24: [8, 1, 7, 0] (out_number r1; exit)
+    let mut machine = Machine::new(&[
+        4, 1, 65, 0, 6, 1, 4, 3, 4, 0, 4, 5, 24, 0, 3, 4, 5, 2, 3, 4, 4, 0, 4, 0, 8, 1, 7, 0,
+    ])
+    .unwrap();
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    // The instruction at 4 is decoded again once overwritten
+    assert_eq!("A65".as_bytes(), &out[..]);
+}
@@ -0,0 +1,120 @@
+use interpreter::{Error, MEMORY_SIZE, Machine, MachineBuilder};
+
+#[test]
+fn default_configuration() {
+    let machine = MachineBuilder::new().build(&[7]).unwrap();
+    assert_eq!(MEMORY_SIZE, machine.memory().len());
+    assert_eq!([0; 16], *machine.regs());
+    assert_eq!(7, machine.memory()[0]);
+}
+
+#[test]
+fn larger_memory() {
+    // 0: loadimm r1 <- #-1
+    // 4: loadimm r2 <- #8192
+    // 8: store [r2] <- r1
+    // 11: exit
+    let mut machine = Machine::builder()
+        .memory_size(65536)
+        .build(&[4, 1, 0xff, 0xff, 4, 2, 0, 0x20, 2, 2, 1, 7])
+        .unwrap();
+    assert_eq!(65536, machine.memory().len());
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert_eq!([0xff; 4], machine.memory()[8192..8196]);
+}
+
+#[test]
+fn smaller_memory() {
+    // 0: loadimm r2 <- #16
+    // 4: store [r2] <- r2
+    // 7: exit
+    let mut machine = Machine::builder()
+        .memory_size(16)
+        .build(&[4, 2, 16, 0, 2, 2, 2, 7])
+        .unwrap();
+    let err = machine.run_on(&mut Vec::new()).unwrap_err();
+    assert!(matches!(err.cause(), Error::MemoryOverflow { address: 16 }));
+}
+
+#[test]
+fn load_address() {
+    let mut machine = Machine::builder()
+        .load_address(0x100)
+        .build(&[8, 10, 7])
+        .unwrap();
+    assert_eq!(0x100, machine.regs()[0]);
+    assert_eq!([0; 0x100], machine.memory()[..0x100]);
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(b"0", &out[..]);
+}
+
+#[test]
+fn initial_registers() {
+    let mut machine = Machine::builder()
+        .load_address(0x100)
+        .register(0, 0x101)
+        .register(10, 42)
+        .build(&[6, 8, 10, 7])
+        .unwrap();
+    assert_eq!(42, machine.regs()[10]);
+    let mut out = Vec::new();
+    machine.run_on(&mut out).unwrap();
+    assert_eq!(b"42", &out[..]);
+}
+
+#[test]
+fn image_too_large() {
+    let result = Machine::builder()
+        .memory_size(16)
+        .load_address(8)
+        .build(&[0; 9]);
+    assert!(matches!(
+        result,
+        Err(Error::ProgramTooLarge {
+            size: 17,
+            memory_size: 16
+        })
+    ));
+}
+
+#[test]
+fn load_address_overflow() {
+    let result = Machine::builder()
+        .memory_size(16)
+        .load_address(usize::MAX)
+        .build(&[7]);
+    assert!(matches!(result, Err(Error::ProgramTooLarge { .. })));
+}
+
+#[test]
+fn invalid_register() {
+    let result = Machine::builder().register(16, 1).build(&[7]);
+    assert!(matches!(result, Err(Error::RegistreOverdepass { reg: 16 })));
+}
+
+#[test]
+fn position_independent_code() {
+    // Counting down with relative branches works wherever it is loaded
+    let program = interpreter::assemble(
+        "
+        loadimm r1 <- #3
+        loadimm r3 <- #1
+      loop:
+        out_number r1
+        sub r1 <- r1 - r3
+        bne r1, r4, #loop
+        exit
+    ",
+    )
+    .unwrap();
+    for address in [0, 0x100, 0x789] {
+        let mut machine = Machine::builder()
+            .load_address(address)
+            .build(&program)
+            .unwrap();
+        let mut out = Vec::new();
+        machine.run_on(&mut out).unwrap();
+        assert_eq!(b"321", &out[..]);
+    }
+}
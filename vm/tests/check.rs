@@ -0,0 +1,131 @@
+use interpreter::{MEMORY_SIZE, Object, Problem, assemble, check, check_object};
+
+#[test]
+fn shipped_programs_have_no_errors() {
+    for path in [
+        "examples/99bottles",
+        "examples/factorial",
+        "examples/gradient",
+        "tests/rfact",
+        "tests/rfact_tr",
+    ] {
+        let binary = std::fs::read(format!("{path}.bin")).unwrap();
+        let problems = check(&binary, MEMORY_SIZE);
+        assert!(
+            !problems.iter().any(Problem::is_error),
+            "{path}: {problems:?}"
+        );
+    }
+}
+
+#[test]
+fn follow_constant_jumps() {
+    // The invalid opcode is skipped by the jump, and the string only follows
+    // the exit
+    let program = assemble(
+        "
+        loadimm r3 <- #skip
+        move r0 <- r3 if r1 != 0
+        loadimm r0 <- #skip
+        [42]
+      skip:
+        exit
+        b'data'
+    ",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            Problem::Unreachable { start: 12, end: 13 },
+            Problem::Unreachable { start: 14, end: 18 }
+        ],
+        check(&program, MEMORY_SIZE)
+    );
+}
+
+#[test]
+fn follow_calls_and_branches() {
+    let program = assemble(
+        "
+        loadimm r2 <- #4096
+        call #function
+        beq r1, r4, #end
+        [42]
+      end:
+        exit
+      function:
+        [6, 17]
+    ",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            Problem::InvalidOpcode {
+                address: 12,
+                opcode: 42
+            },
+            Problem::InvalidRegister {
+                address: 14,
+                reg: 17
+            }
+        ],
+        check(&program, MEMORY_SIZE)
+    );
+}
+
+#[test]
+fn follow_return_addresses() {
+    // The return address is stored before jumping, as compiled programs do
+    let program = assemble(
+        "
+        loadimm r3 <- #back
+        store [r2] <- r3
+        loadimm r0 <- #function
+      back:
+        [99]
+      function:
+        load r0 <- [r2]
+    ",
+    )
+    .unwrap();
+    assert_eq!(
+        vec![Problem::InvalidOpcode {
+            address: 11,
+            opcode: 99
+        }],
+        check(&program, MEMORY_SIZE)
+    );
+}
+
+#[test]
+fn report_instructions_outside_memory() {
+    let program = assemble("loadimm r0 <- #-1").unwrap();
+    assert_eq!(
+        vec![Problem::InvalidTarget {
+            address: 0,
+            target: u32::MAX as usize
+        }],
+        check(&program, MEMORY_SIZE)
+    );
+    // 0: out r1
+    // 2: loadimm r1 <- ... (truncated)
+    assert_eq!(
+        vec![Problem::CrossesMemoryEnd { address: 2 }],
+        check(&[6, 1, 4, 1], 4)
+    );
+}
+
+#[test]
+fn report_entry_outside_memory() {
+    let object = Object {
+        entry: 16,
+        ..Object::from_image(&[7])
+    };
+    assert_eq!(
+        vec![
+            Problem::Unreachable { start: 0, end: 1 },
+            Problem::EntryOutsideMemory { entry: 16 }
+        ],
+        check_object(&object, 16)
+    );
+}
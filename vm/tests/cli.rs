@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use interpreter::{Object, Section, SectionKind};
 
 #[test]
 fn no_filename() {
@@ -21,6 +22,27 @@ fn invalid_file_content() {
     command.arg("Cargo.toml").assert().failure();
 }
 
+#[test]
+fn readable_diagnostic() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command.arg("Cargo.toml").output().unwrap();
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    vm: Cargo.toml: unrecognized file format, neither an object file nor a program image
+    "###);
+}
+
+#[test]
+fn reject_markdown() {
+    // '#' is the opcode of `iret`, but what follows does not decode
+    let text = std::env::temp_dir().join("vm-cli-readme.md");
+    std::fs::write(&text, "# Virtual machine\n").unwrap();
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command.arg(&text).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.contains("unrecognized file format"), "{stderr}");
+}
+
 // Execute a virtual machine program and return the result
 fn exec(bin: &str) -> String {
     let mut command = Command::cargo_bin("vm").unwrap();
@@ -60,3 +82,409 @@ fn execute_example_factorial() {
     I'm done!
     "###);
 }
+
+#[test]
+fn assemble_listing() {
+    let output = std::env::temp_dir().join("vm-cli-hello_world.bin");
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command
+        .args(["asm", "examples/hello_world.dis", "-o"])
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(
+        std::fs::read("examples/hello_world.bin").unwrap(),
+        std::fs::read(&output).unwrap()
+    );
+}
+
+#[test]
+fn assemble_invalid_listing() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    // Cargo.toml is not a valid listing either
+    command
+        .args(["asm", "Cargo.toml", "-o", "/dev/null"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn disassemble_binary() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .args(["disasm", "tests/push_pop.bin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        std::fs::read_to_string("tests/push_pop.dis").unwrap(),
+        String::from_utf8(output.stdout).unwrap()
+    );
+}
+
+#[test]
+fn debug_with_labels_from_listing() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    // The labels of tests/rfact.dis are picked up automatically
+    let output = command
+        .args(["debug", "tests/rfact.bin"])
+        .write_stdin("break rfact\nquit\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("breakpoint at 0087 <rfact>")
+    );
+}
+
+#[test]
+fn trace_execution() {
+    let trace = std::env::temp_dir().join("vm-cli-push_pop.trace");
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command
+        .arg("--trace")
+        .arg(&trace)
+        .arg("tests/push_pop.bin")
+        .assert()
+        .success();
+    let trace = std::fs::read_to_string(trace).unwrap();
+    assert_eq!(18, trace.lines().count());
+    assert_eq!(
+        Some("0012   store [r2] <- r0 ; [4092] = 0f 00 00 00"),
+        trace.lines().nth(3)
+    );
+}
+
+#[test]
+fn stop_after_max_steps() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .args(["--max-steps", "20", "examples/hello_world.bin"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    vm: examples/hello_world.bin: stopped after executing 20 instructions
+    "###);
+}
+
+#[test]
+fn program_larger_than_memory_size() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .args(["--memory-size", "16", "examples/hello_world.bin"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    vm: examples/hello_world.bin: program needing 162 bytes does not fit in memory (16 bytes)
+    "###);
+}
+
+#[test]
+fn write_framebuffer_as_ppm() {
+    let dir = std::env::temp_dir().join("vm-cli-gradient");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command
+        .arg(format!("--fb=ppm:{}", dir.display()))
+        .arg("examples/gradient.bin")
+        .assert()
+        .success();
+    let image = std::fs::read(dir.join("frame_0000.ppm")).unwrap();
+    assert_eq!(11 + 192, image.len());
+    assert!(!dir.join("frame_0001.ppm").exists());
+}
+
+#[test]
+fn invalid_framebuffer_output() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command
+        .args(["--fb=png", "examples/gradient.bin"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn save_and_resume() {
+    let snapshot = std::env::temp_dir().join("vm-cli-99bottles.snap");
+    let full = Command::cargo_bin("vm")
+        .unwrap()
+        .arg("examples/99bottles.bin")
+        .output()
+        .unwrap()
+        .stdout;
+    let first = Command::cargo_bin("vm")
+        .unwrap()
+        .args(["--max-steps", "1000", "--save-on-exit"])
+        .arg(&snapshot)
+        .arg("examples/99bottles.bin")
+        .output()
+        .unwrap();
+    assert!(!first.status.success());
+    let rest = Command::cargo_bin("vm")
+        .unwrap()
+        .arg("--resume")
+        .arg(&snapshot)
+        .output()
+        .unwrap();
+    assert!(rest.status.success());
+    assert_eq!(full, [first.stdout, rest.stdout].concat());
+}
+
+#[test]
+fn resume_invalid_snapshot() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command.args(["--resume", "Cargo.toml"]).output().unwrap();
+    assert!(!output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    vm: Cargo.toml: invalid snapshot: not a snapshot
+    "###);
+}
+
+#[test]
+fn profile_with_labels_from_listing() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .args(["--profile", "examples/hello_world.bin"])
+        .output()
+        .unwrap();
+    assert_eq!(b"Hello, world!\n", &output.stdout[..]);
+    insta::assert_snapshot!(String::from_utf8(output.stderr).unwrap(), @r###"
+    159 instructions executed
+
+    instructions by kind:
+          count       %  kind
+             72  45.28%  loadimm
+             37  23.27%  sub
+             17  10.69%  load
+             15   9.43%  move
+             14   8.81%  out
+              3   1.89%  store
+              1   0.63%  exit
+
+    hottest addresses:
+          count       %  address
+             15   9.43%  0092 <print>   loadimm r8 <- #104
+             15   9.43%  0096 <print+4>   move r0 <- r8 if r11 != 0
+             14   8.81%  0104 <ite_then_1>   load r3 <- [r10]
+             14   8.81%  0107 <ite_then_1+3>   out r3
+             14   8.81%  0109 <ite_then_1+5>   loadimm r3 <- #-1
+             14   8.81%  0113 <ite_then_1+9>   sub r10 <- r10 - r3
+             14   8.81%  0117 <ite_then_1+13>   loadimm r3 <- #1
+             14   8.81%  0121 <ite_then_1+17>   sub r11 <- r11 - r3
+             14   8.81%  0125 <ite_then_1+21>   loadimm r0 <- #92
+              1   0.63%  0000   loadimm r2 <- #4096
+              1   0.63%  0004   loadimm r3 <- #4
+              1   0.63%  0008   sub r2 <- r2 - r3
+              1   0.63%  0012   store [r2] <- r10
+              1   0.63%  0015   loadimm r3 <- #4
+              1   0.63%  0019   sub r2 <- r2 - r3
+              1   0.63%  0023   store [r2] <- r11
+              1   0.63%  0026   loadimm r10 <- #148
+              1   0.63%  0030   loadimm r11 <- #14
+              1   0.63%  0034   loadimm r3 <- #4
+              1   0.63%  0038   sub r2 <- r2 - r3
+
+    hottest blocks:
+     instructions       %  executions  block
+               98  61.64%          14  0104 <ite_then_1> - 0125
+               28  17.61%          14  0092 <print> - 0096
+               14   8.81%           1  0000 - 0049
+               11   6.92%           1  0053 <return_from_print_1> - 0091
+                5   3.14%           1  0129 <ite_end_1> - 0145
+                3   1.89%           1  0092 <print> - 0100
+
+    functions:
+          total       %         own       %   calls  function
+            159 100.00%          25  15.72%       1  0000
+            134  84.28%         134  84.28%       1  0092 <print>
+    "###);
+}
+
+#[test]
+fn write_lcov_coverage() {
+    let lcov = std::env::temp_dir().join("vm-cli-hello_world.info");
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .arg("--coverage")
+        .arg("--lcov")
+        .arg(&lcov)
+        .arg("examples/hello_world.bin")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.ends_with("coverage: 40/40 instructions (100.00%)\n"));
+    let lcov = std::fs::read_to_string(lcov).unwrap();
+    assert!(lcov.starts_with("TN:\nSF:examples/hello_world.dis\nDA:1,1\n"));
+    assert!(lcov.ends_with("LF:40\nLH:40\nend_of_record\n"));
+}
+
+#[test]
+fn coverage_needs_listing() {
+    let program = std::env::temp_dir().join("vm-cli-unlisted.bin");
+    std::fs::copy("examples/hello_world.bin", &program).unwrap();
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command.arg("--coverage").arg(&program).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("vm-cli-unlisted.dis"), "{stderr}");
+}
+
+#[test]
+fn check_program() {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command
+        .args(["check", "examples/hello_world.bin"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    insta::assert_snapshot!(String::from_utf8(output.stdout).unwrap(), @r###"
+    examples/hello_world.bin: warning: at addresses 0148-0161: unreachable
+    "###);
+}
+
+#[test]
+fn check_invalid_program() {
+    let program = std::env::temp_dir().join("vm-cli-invalid.bin");
+    // 0: out r20
+    // 2: exit
+    std::fs::write(&program, [6, 20, 7]).unwrap();
+    let mut command = Command::cargo_bin("vm").unwrap();
+    let output = command.arg("check").arg(&program).output().unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let path = program.display().to_string();
+    insta::assert_snapshot!(stdout.replace(&path, "PROGRAM"), @r###"
+    PROGRAM: error: at address 0000: register r20 does not exist
+    PROGRAM: warning: at addresses 0002-0002: unreachable
+    "###);
+    assert_eq!(format!("vm: {path}: 1 error found\n"), stderr);
+
+    // Only running a program requires it to start with a valid instruction
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command.arg("disasm").arg(&program).assert().success();
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command.arg("debug").arg(&program).write_stdin("quit\n");
+    command.assert().success();
+}
+
+#[test]
+fn assemble_and_run_object_file() {
+    let object = std::env::temp_dir().join("vm-cli-hello_world.vmo");
+    Command::cargo_bin("vm")
+        .unwrap()
+        .args(["asm", "--object", "examples/hello_world.dis", "-o"])
+        .arg(&object)
+        .assert()
+        .success();
+    let output = Command::cargo_bin("vm")
+        .unwrap()
+        .arg(&object)
+        .output()
+        .unwrap();
+    assert_eq!(b"Hello, world!\n", &output.stdout[..]);
+    let output = Command::cargo_bin("vm")
+        .unwrap()
+        .arg("disasm")
+        .arg(&object)
+        .output()
+        .unwrap();
+    assert_eq!(exec_disasm("examples/hello_world.bin"), output.stdout);
+}
+
+fn exec_disasm(program: &str) -> Vec<u8> {
+    let mut command = Command::cargo_bin("vm").unwrap();
+    command.args(["disasm", program]).output().unwrap().stdout
+}
+
+#[test]
+fn link_with_stdlib() {
+    let listing = std::env::temp_dir().join("vm-cli-greet.dis");
+    let object = std::env::temp_dir().join("vm-cli-greet.vmo");
+    std::fs::write(
+        &listing,
+        "loadimm r2 <- #4096\nloadimm r10 <- #42\ncall #print_number\nexit\n",
+    )
+    .unwrap();
+    Command::cargo_bin("vm")
+        .unwrap()
+        .args(["link", "--no-stdlib"])
+        .arg(&listing)
+        .arg("-o")
+        .arg(&object)
+        .assert()
+        .failure()
+        .stderr("vm: undefined symbol `print_number`\n");
+    Command::cargo_bin("vm")
+        .unwrap()
+        .arg("link")
+        .arg(&listing)
+        .args(["examples/hello_world.bin", "-o"])
+        .arg(&object)
+        .assert()
+        .failure()
+        .stderr("vm: examples/hello_world.bin: not an object file, raw images cannot be linked\n");
+    Command::cargo_bin("vm")
+        .unwrap()
+        .arg("link")
+        .arg(&listing)
+        .arg("-o")
+        .arg(&object)
+        .assert()
+        .success();
+    Command::cargo_bin("vm")
+        .unwrap()
+        .arg(&object)
+        .assert()
+        .success()
+        .stdout("42");
+}
+
+#[test]
+fn object_beyond_memory() {
+    let object = std::env::temp_dir().join("vm-cli-far.vmo");
+    let far = Object {
+        sections: vec![Section {
+            kind: SectionKind::Code,
+            address: 0xffff_0000,
+            bytes: vec![7],
+        }],
+        ..Object::default()
+    };
+    std::fs::write(&object, far.encode()).unwrap();
+    let message = format!(
+        "vm: {}: program needing 4294901761 bytes does not fit in memory (4096 bytes)\n",
+        object.display()
+    );
+    for command in [None, Some("check"), Some("disasm")] {
+        let mut vm = Command::cargo_bin("vm").unwrap();
+        vm.args(command).arg(&object);
+        vm.assert().failure().stderr(message.clone());
+    }
+}
+
+#[test]
+fn assemble_with_relative_include() {
+    let dir = std::env::temp_dir().join("vm-cli-include/sub");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("main.dis"),
+        "loadimm r1 <- #0\n.include \"inc.dis\"\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("inc.dis"), "exit\n").unwrap();
+    let output = dir.join("main.bin");
+    Command::cargo_bin("vm")
+        .unwrap()
+        .arg("asm")
+        .arg(dir.join("main.dis"))
+        .arg("-o")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(vec![4, 1, 0, 0, 7], std::fs::read(&output).unwrap());
+}
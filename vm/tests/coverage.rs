@@ -0,0 +1,66 @@
+use interpreter::{Coverage, Machine, Profile, assemble};
+
+const LISTING: &str = "\
+  loadimm r1 <- #3
+  loadimm r3 <- #1
+loop:
+  sub r1 <- r1 - r3
+  bne r1, r4, #loop
+  exit
+error:
+  out_number r1
+  exit
+  [1, 2, 3]
+";
+
+fn coverage() -> Coverage {
+    let mut machine = Machine::new(&assemble(LISTING).unwrap()).unwrap();
+    machine.set_profile(Some(Profile::new()));
+    machine.run().unwrap();
+    Coverage::new(LISTING, &machine.set_profile(None).unwrap()).unwrap()
+}
+
+#[test]
+fn annotate_listing() {
+    let coverage = coverage();
+    assert_eq!(7, coverage.instructions());
+    assert_eq!(5, coverage.covered());
+    assert_eq!(vec![8, 9], coverage.missed());
+    insta::assert_snapshot!(coverage.annotate(), @r###"
+            1 | loadimm r1 <- #3
+            1 |   loadimm r3 <- #1
+              | loop:
+            3 |   sub r1 <- r1 - r3
+            3 |   bne r1, r4, #loop
+            1 |   exit
+              | error:
+        ##### |   out_number r1
+        ##### |   exit
+              |   [1, 2, 3]
+    coverage: 5/7 instructions (71.43%)
+    "###);
+}
+
+#[test]
+fn write_lcov() {
+    insta::assert_snapshot!(coverage().lcov("tests/loop.dis"), @r###"
+    TN:
+    SF:tests/loop.dis
+    DA:1,1
+    DA:2,1
+    DA:4,3
+    DA:5,3
+    DA:6,1
+    DA:8,0
+    DA:9,0
+    LF:7
+    LH:5
+    end_of_record
+    "###);
+}
+
+#[test]
+fn reject_invalid_listing() {
+    let error = Coverage::new("jump #0", &Profile::new()).err().unwrap();
+    assert_eq!(1, error.line);
+}
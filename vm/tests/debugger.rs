@@ -0,0 +1,76 @@
+use interpreter::{Debugger, Machine, assemble_with_labels};
+
+// Run the debugger on a test program with the given commands and return
+// its output
+fn debug(program: &str, commands: &str) -> String {
+    let listing = std::fs::read_to_string(format!("tests/{program}.dis")).unwrap();
+    let (image, labels) = assemble_with_labels(&listing).unwrap();
+    let mut machine = Machine::new(&image).unwrap();
+    machine.set_history(100);
+    let mut debugger = Debugger::new(machine, labels);
+    let mut output = Vec::new();
+    debugger.run(commands.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn break_on_label() {
+    insta::assert_snapshot!(debug("rfact", "b rfact\nset r10 3\nc\nc\nr\ns 2\nd rfact\nc\nc\n"), @r###"
+    0000   loadimm r2 <- #4096
+    (vm) breakpoint at 0087 <rfact>
+    (vm) (vm) breakpoint reached
+    0087 <rfact>   loadimm r8 <- #1
+    (vm) breakpoint reached
+    0087 <rfact>   loadimm r8 <- #1
+    (vm) r0  = 0x00000057          87    r1  = 0x00000000           0
+    r2  = 0x00000ff4        4084    r3  = 0x00000095         149
+    r4  = 0x00000000           0    r5  = 0x00000000           0
+    r6  = 0x00000000           0    r7  = 0x00000000           0
+    r8  = 0x00000002           2    r9  = 0x0000006f         111
+    r10 = 0x00000002           2    r11 = 0x00000000           0
+    r12 = 0x00000000           0    r13 = 0x00000000           0
+    r14 = 0x00000000           0    r15 = 0x00000000           0
+    (vm) 0095   loadimm r9 <- #111
+    (vm) deleted breakpoint at 0087 <rfact>
+    (vm) program exited
+    the program is not running
+    (vm) the program is not running
+    (vm)
+    "###);
+}
+
+#[test]
+fn inspect_memory() {
+    insta::assert_snapshot!(debug("push_pop", "s 4\nx r2 16\nmem 0x10 8\nx nowhere\nset r1 -1\nset r16 0\nq\n"), @r###"
+    0000   loadimm r2 <- #4096
+    (vm) 0015   loadimm r3 <- #4
+    (vm) 0ff0: 00 00 00 00 00 00 00 00 00 00 00 00 0f 00 00 00
+    (vm) 0010: 03 04 00 05 02 02 03 02
+    (vm) invalid memory range `nowhere 64`
+    (vm) (vm) invalid register or value
+    (vm) 
+    "###);
+}
+
+#[test]
+fn step_back() {
+    insta::assert_snapshot!(debug("push_pop", "s 4\nback 2\nr\nback 5\nc\nback\n"), @r###"
+    0000   loadimm r2 <- #4096
+    (vm) 0015   loadimm r3 <- #4
+    (vm) 0008   sub r2 <- r2 - r3
+    (vm) r0  = 0x00000008           8    r1  = 0x00000000           0
+    r2  = 0x00001000        4096    r3  = 0x00000004           4
+    r4  = 0x00000000           0    r5  = 0x00000000           0
+    r6  = 0x00000000           0    r7  = 0x00000000           0
+    r8  = 0x00000000           0    r9  = 0x00000000           0
+    r10 = 0x00000000           0    r11 = 0x00000000           0
+    r12 = 0x00000000           0    r13 = 0x00000000           0
+    r14 = 0x00000000           0    r15 = 0x00000000           0
+    (vm) no more history after undoing 2 instructions
+    0000   loadimm r2 <- #4096
+    (vm) program exited
+    the program is not running
+    (vm) 0064   exit
+    (vm)
+    "###);
+}
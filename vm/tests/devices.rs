@@ -0,0 +1,160 @@
+use interpreter::{Console, Device, Error, Machine, Random, TickCounter, assemble};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Output buffer which can still be read once given to a console.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn console() {
+    // Echo the input in upper case through a console mapped at 0x8000,
+    // outside of memory
+    let program = assemble(
+        "
+        loadimm r1 <- #0x4000
+        add r1 <- r1 + r1
+        loadimm r3 <- #32
+        loadimm r4 <- #-1
+      loop:
+        load r5 <- [r1]
+        beq r5, r4, #end
+        sub r5 <- r5 - r3
+        store [r1] <- r5
+        loadimm r0 <- #loop
+      end:
+        exit
+    ",
+    )
+    .unwrap();
+    let output = Shared::default();
+    let mut machine = Machine::new(&program).unwrap();
+    machine
+        .map_device(0x8000, Box::new(Console::new(&b"abc"[..], output.clone())))
+        .unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(b"ABC", &output.0.borrow()[..]);
+}
+
+#[test]
+fn tick_counter() {
+    // 0: loadimm r1 <- #256
+    // 4: loadimm r3 <- #1
+    // 8: load r2 <- [r1]
+    // 11: exit
+    let mut machine = Machine::new(&[4, 1, 0, 1, 4, 3, 1, 0, 3, 2, 1, 7]).unwrap();
+    machine
+        .map_device(256, Box::new(TickCounter::new()))
+        .unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    // Two instructions were executed before the load
+    assert_eq!(2, machine.regs()[2]);
+}
+
+#[test]
+fn read_only_tick_counter() {
+    // 0: store [r1] <- r1
+    let mut machine = Machine::new(&[2, 1, 1]).unwrap();
+    machine.set_reg(1, 256).unwrap();
+    machine
+        .map_device(256, Box::new(TickCounter::new()))
+        .unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::UnsupportedAccess));
+}
+
+#[test]
+fn seeded_random() {
+    let mut first = Random::new(42);
+    let mut second = Random::new(42);
+    let numbers: Vec<u32> = (0..8).map(|_| first.load(0).unwrap()).collect();
+    assert_eq!(
+        numbers,
+        (0..8).map(|_| second.load(0).unwrap()).collect::<Vec<_>>()
+    );
+    assert_ne!(numbers[0], numbers[1]);
+    // Seeding again restarts the sequence
+    first.store(0, 42).unwrap();
+    assert_eq!(numbers[0], first.load(0).unwrap());
+    assert_ne!(numbers[0], Random::new(43).load(0).unwrap());
+    // 0 is a valid seed
+    assert_ne!(0, Random::new(0).load(0).unwrap());
+}
+
+#[test]
+fn device_shadows_memory() {
+    // 0: load r2 <- [r1]
+    // 3: [1, 2, 3, 4]
+    let mut machine = Machine::new(&[3, 2, 1, 1, 2, 3, 4]).unwrap();
+    machine.set_reg(1, 3).unwrap();
+    machine.map_device(3, Box::new(Random::new(1))).unwrap();
+    machine.step().unwrap();
+    assert_eq!(Random::new(1).load(0).unwrap(), machine.regs()[2]);
+}
+
+#[test]
+fn refuse_overlapping_devices() {
+    let mut machine = Machine::new(&[]).unwrap();
+    machine
+        .map_device(0x100, Box::new(TickCounter::new()))
+        .unwrap();
+    let result = machine.map_device(0x104, Box::new(Random::new(1)));
+    assert!(matches!(
+        result,
+        Err(Error::DeviceOverlap { address: 0x104 })
+    ));
+    machine.map_device(0x108, Box::new(Random::new(1))).unwrap();
+    machine.map_device(0xfc, Box::new(Random::new(1))).unwrap();
+    let result = machine.map_device(usize::MAX - 1, Box::new(Random::new(1)));
+    assert!(matches!(
+        result,
+        Err(Error::DeviceOverlap { address }) if address == usize::MAX - 1
+    ));
+}
+
+/// Device remembering the last value written at each of its 4 words.
+struct Registers([u32; 4]);
+
+impl Device for Registers {
+    fn size(&self) -> usize {
+        16
+    }
+
+    fn load(&mut self, offset: usize) -> Result<u32, Error> {
+        Ok(self.0[offset / 4])
+    }
+
+    fn store(&mut self, offset: usize, value: u32) -> Result<(), Error> {
+        self.0[offset / 4] = value;
+        Ok(())
+    }
+}
+
+#[test]
+fn custom_device() {
+    // 0: store [r1] <- r3
+    // 3: load r4 <- [r1]
+    // 6: exit
+    let mut machine = Machine::new(&[2, 1, 3, 3, 4, 1, 7]).unwrap();
+    machine.set_reg(1, 0x808).unwrap();
+    machine.set_reg(3, 42).unwrap();
+    machine
+        .map_device(0x800, Box::new(Registers([0; 4])))
+        .unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(42, machine.regs()[4]);
+    // The memory is left untouched
+    assert!(machine.memory().iter().skip(7).all(|&b| b == 0));
+}
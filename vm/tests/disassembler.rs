@@ -0,0 +1,170 @@
+use interpreter::{assemble, assemble_object, disassemble, disassemble_object};
+
+const PROGRAMS: [&str; 14] = [
+    "examples/99bottles",
+    "examples/count",
+    "examples/factorial",
+    "examples/fibonacci",
+    "examples/gradient",
+    "examples/hello_world",
+    "tests/afact",
+    "tests/fact",
+    "tests/fibo",
+    "tests/function",
+    "tests/multiply",
+    "tests/push_pop",
+    "tests/rfact",
+    "tests/rfact_tr",
+];
+
+// Keep only what does not depend on label names: label definitions are
+// dropped and label references replaced by a placeholder
+fn without_label_names(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .filter(|line| !line.ends_with(':'))
+        .map(|line| match line.split_once('#') {
+            Some((head, imm)) if imm.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                format!("{head}#label")
+            }
+            _ => line.to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn round_trip_binaries() {
+    for path in PROGRAMS {
+        let binary = std::fs::read(format!("{path}.bin")).unwrap();
+        assert_eq!(binary, assemble(&disassemble(&binary)).unwrap(), "{path}");
+    }
+}
+
+#[test]
+fn match_shipped_listings() {
+    for path in PROGRAMS {
+        let binary = std::fs::read(format!("{path}.bin")).unwrap();
+        let listing = std::fs::read_to_string(format!("{path}.dis")).unwrap();
+        assert_eq!(
+            without_label_names(&listing),
+            without_label_names(&disassemble(&binary)),
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn synthesize_labels() {
+    let binary = assemble(
+        "
+        loadimm r3 <- #4
+        loadimm r10 <- #msg
+        loadimm r8 <- #done
+        move r0 <- r8 if r3 != 0
+        out r10
+      done:
+        exit
+      msg:
+        b'it\\'s\\x01'
+        [0, 0]
+    ",
+    )
+    .unwrap();
+    insta::assert_snapshot!(disassemble(&binary), @r###"
+      0000   loadimm r3 <- #4
+      0004   loadimm r10 <- #data_0019
+      0008   loadimm r8 <- #label_0018
+      0012   move r0 <- r8 if r3 != 0
+      0016   out r10
+    label_0018:
+      0018   exit
+    data_0019:
+      ???? b"it's\x01\x00\x00"
+    "###);
+}
+
+#[test]
+fn label_call_targets() {
+    let binary = assemble(
+        "
+        loadimm r2 <- #4096
+        loadimm r10 <- #42
+        call #show
+        exit
+      show:
+        push r10
+        out_number r10
+        pop r10
+        ret
+    ",
+    )
+    .unwrap();
+    insta::assert_snapshot!(disassemble(&binary), @r###"
+      0000   loadimm r2 <- #4096
+      0004   loadimm r10 <- #42
+      0008   call #label_0012
+      0011   exit
+    label_0012:
+      0012   push r10
+      0014   out_number r10
+      0016   pop r10
+      0018   ret
+    "###);
+}
+
+#[test]
+fn label_branch_targets() {
+    let binary = assemble(
+        "
+        loadimm r1 <- #10
+        loadimm r3 <- #1
+      loop:
+        out_number r1
+        sub r1 <- r1 - r3
+        bne r1, r4, #loop
+        exit
+    ",
+    )
+    .unwrap();
+    insta::assert_snapshot!(disassemble(&binary), @r###"
+      0000   loadimm r1 <- #10
+      0004   loadimm r3 <- #1
+    label_0008:
+      0008   out_number r1
+      0010   sub r1 <- r1 - r3
+      0014   bne r1, r4, #label_0008
+      0019   exit
+    "###);
+}
+
+#[test]
+fn disassemble_object_sections() {
+    // The data decodes as `exit`, and the routine is out of the way
+    let object = assemble_object(
+        "
+        loadimm r10 <- #value
+        call #routine
+        exit
+      value:
+        .byte 7
+        .org 32
+      routine:
+        out_number r10
+        ret
+    ",
+    )
+    .unwrap();
+    let listing = disassemble_object(&object);
+    insta::assert_snapshot!(listing, @r###"
+      0000   loadimm r10 <- #data_0008
+      0004   call #label_0032
+      0007   exit
+    data_0008:
+      ???? b'\x07'
+    .org 32
+    label_0032:
+      0032   out_number r10
+      0034   ret
+    "###);
+    assert_eq!(object.image(), assemble(&listing).unwrap());
+}
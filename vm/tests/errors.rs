@@ -0,0 +1,116 @@
+use interpreter::{Error, MEMORY_SIZE, Machine};
+use std::io::{self, Write};
+
+#[test]
+fn too_large_program() {
+    let error = Machine::new(&[0; MEMORY_SIZE + 1]).err().unwrap();
+    assert!(
+        matches!(error, Error::ProgramTooLarge { size, memory_size } if size == MEMORY_SIZE + 1 && memory_size == MEMORY_SIZE)
+    );
+    assert_eq!(None, error.ip());
+}
+
+#[test]
+fn locate_faulting_instruction() {
+    // 0: loadimm r1 <- #30000
+    // 4: store [r1] <- r1
+    let mut machine = Machine::new(&[4, 1, 0x30, 0x75, 2, 1, 1]).unwrap();
+    machine.step().unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(
+        error,
+        Error::Fault {
+            ip: 4,
+            opcode: Some(2),
+            ..
+        }
+    ));
+    assert!(matches!(
+        error.cause(),
+        Error::MemoryOverflow { address: 30000 }
+    ));
+    assert_eq!(
+        "at address 0004 (opcode 2): memory access at address 30000 is out of bounds",
+        error.to_string()
+    );
+}
+
+#[test]
+fn report_invalid_register_and_opcode() {
+    let mut machine = Machine::new(&[6, 42]).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(
+        error.cause(),
+        Error::RegistreOverdepass { reg: 42 }
+    ));
+
+    let mut machine = Machine::new(&[0x2a]).unwrap();
+    let error = machine.step().unwrap_err();
+    assert_eq!(
+        "at address 0000 (opcode 42): invalid opcode 42",
+        error.to_string()
+    );
+
+    let mut machine = Machine::new(&[]).unwrap();
+    machine.set_reg(0, MEMORY_SIZE as u32).unwrap();
+    let error = machine.step().unwrap_err();
+    assert_eq!(Some(MEMORY_SIZE), error.ip());
+    assert!(matches!(error, Error::Fault { opcode: None, .. }));
+
+    assert!(matches!(
+        machine.set_reg(16, 0),
+        Err(Error::RegistreOverdepass { reg: 16 })
+    ));
+}
+
+#[test]
+fn report_division_by_zero() {
+    // 0: loadimm r1 <- #7
+    // 4: div r1 <- r1 / r2
+    let mut machine = Machine::new(&[4, 1, 7, 0, 13, 1, 1, 2]).unwrap();
+    machine.step().unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::DivisionByZero));
+    assert_eq!(
+        "at address 0004 (opcode 13): division by zero",
+        error.to_string()
+    );
+    // The destination register is left untouched
+    assert_eq!(7, machine.regs()[1]);
+}
+
+#[test]
+fn access_last_word_of_memory() {
+    // 0: store [r1] <- r1
+    // 3: load r2 <- [r1]
+    for address in MEMORY_SIZE - 4..MEMORY_SIZE {
+        let mut machine = Machine::new(&[2, 1, 1, 3, 2, 1]).unwrap();
+        machine.set_reg(1, address as u32).unwrap();
+        let fits = address + 4 <= MEMORY_SIZE;
+        assert_eq!(fits, machine.step().is_ok());
+    }
+}
+
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn keep_output_error() {
+    // 0: out r0
+    let mut machine = Machine::new(&[6, 0]).unwrap();
+    let error = machine.step_on(&mut BrokenPipe).unwrap_err();
+    match error.cause() {
+        Error::OutputError(e) => assert_eq!(io::ErrorKind::BrokenPipe, e.kind()),
+        _ => panic!(),
+    }
+    assert!(std::error::Error::source(&error).is_some());
+}
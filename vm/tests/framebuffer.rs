@@ -0,0 +1,70 @@
+use interpreter::{
+    Device, FRAME_SIZE, FRAMEBUFFER_ADDRESS, Frame, Framebuffer, Machine, render_ansi, render_ppm,
+};
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+/// Framebuffer keeping a copy of every presented frame.
+fn recording() -> (Framebuffer, Rc<RefCell<Vec<Frame>>>) {
+    let frames = Rc::new(RefCell::new(Vec::new()));
+    let recorded = frames.clone();
+    let framebuffer = Framebuffer::new(move |frame, number| {
+        assert_eq!(number as usize, recorded.borrow().len());
+        recorded.borrow_mut().push(*frame);
+        Ok(())
+    });
+    (framebuffer, frames)
+}
+
+#[test]
+fn layout_like_image() {
+    let (mut framebuffer, frames) = recording();
+    // Pixel (1, 2) is red, pixel (7, 7) is white
+    framebuffer.store(3 * (8 + 2), 0xff).unwrap();
+    framebuffer.store(FRAME_SIZE - 3, 0xffff_ffff).unwrap();
+    assert_eq!(0xff, framebuffer.load(3 * (8 + 2)).unwrap());
+    // Bytes past the frame are neither written nor read
+    assert_eq!(0x00ff_ffff, framebuffer.load(FRAME_SIZE - 3).unwrap());
+
+    assert_eq!(0, framebuffer.load(FRAME_SIZE).unwrap());
+    framebuffer.store(FRAME_SIZE, 1).unwrap();
+    assert_eq!(1, framebuffer.load(FRAME_SIZE).unwrap());
+
+    let frames = frames.borrow();
+    assert_eq!(1, frames.len());
+    let mut expected = [0; FRAME_SIZE];
+    expected[30] = 0xff;
+    expected[FRAME_SIZE - 3..].fill(0xff);
+    assert_eq!(expected, frames[0]);
+}
+
+#[test]
+fn present_from_program() {
+    let mut machine = Machine::new(include_bytes!("../examples/gradient.bin")).unwrap();
+    let (framebuffer, frames) = recording();
+    machine
+        .map_device(FRAMEBUFFER_ADDRESS, Box::new(framebuffer))
+        .unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    let frames = frames.borrow();
+    assert_eq!(1, frames.len());
+    // Pixel i goes from blue to red
+    for (i, pixel) in frames[0].chunks(3).enumerate() {
+        assert_eq!([4 * i as u8, 0, 255 - 4 * i as u8], pixel);
+    }
+}
+
+#[test]
+fn render_frames() {
+    let mut frame = [0; FRAME_SIZE];
+    frame[..3].copy_from_slice(&[255, 128, 0]);
+    let text = render_ansi(&frame);
+    assert_eq!(8, text.lines().count());
+    assert!(text.starts_with("\x1b[48;2;255;128;0m  \x1b[48;2;0;0;0m  "));
+    assert!(text.ends_with("  \x1b[0m\n"));
+
+    let image = render_ppm(&frame);
+    assert_eq!(b"P6\n8 8\n255\n", &image[..11]);
+    assert_eq!(&frame[..], &image[11..]);
+}
@@ -0,0 +1,116 @@
+use interpreter::{Error, Machine, assemble};
+
+#[test]
+fn rewind_to_faulting_instruction() {
+    let program = assemble(
+        "
+        loadimm r1 <- #100
+        loadimm r3 <- #4
+        store [r1] <- r1
+        loadimm r1 <- #30000
+        store [r1] <- r1
+    ",
+    )
+    .unwrap();
+    let mut machine = Machine::new(&program).unwrap();
+    machine.set_history(10);
+    let error = machine.run().unwrap_err();
+    assert!(matches!(error, Error::Fault { ip: 15, .. }));
+    assert_eq!(5, machine.history_len());
+
+    // The failed store is undone too
+    assert!(machine.step_back());
+    assert_eq!(15, machine.regs()[0]);
+    assert_eq!(30000, machine.regs()[1]);
+    assert!(machine.step_back());
+    assert!(machine.step_back());
+    assert_eq!([0; 4], machine.memory()[100..104]);
+    assert_eq!(100, machine.regs()[1]);
+    assert_eq!(2, machine.steps());
+    assert!(machine.step_back());
+    assert!(machine.step_back());
+    assert!(!machine.step_back());
+    assert_eq!([0; 16], machine.regs());
+}
+
+#[test]
+fn run_back_to_address() {
+    // Counts down from 5, printing each value
+    let program = assemble(
+        "
+        loadimm r1 <- #5
+        loadimm r3 <- #1
+      loop:
+        out_number r1
+        sub r1 <- r1 - r3
+        bne r1, r4, #loop
+        exit
+    ",
+    )
+    .unwrap();
+    let mut machine = Machine::new(&program).unwrap();
+    machine.set_history(100);
+    machine.run_on(&mut Vec::new()).unwrap();
+    assert!(machine.run_back_to(8));
+    assert_eq!(1, machine.regs()[1]);
+    assert!(machine.run_back_to(8));
+    assert_eq!(2, machine.regs()[1]);
+    assert!(!machine.run_back_to(100));
+    assert_eq!(0, machine.regs()[0]);
+    assert_eq!(0, machine.steps());
+}
+
+#[test]
+fn bounded_history() {
+    let program = assemble(
+        "
+      loop:
+        loadimm r0 <- #loop
+    ",
+    )
+    .unwrap();
+    let mut machine = Machine::new(&program).unwrap();
+    assert!(!machine.step_back());
+    machine.set_history(3);
+    for _ in 0..10 {
+        machine.step().unwrap();
+    }
+    assert_eq!(3, machine.history_len());
+    machine.set_history(2);
+    assert_eq!(2, machine.history_len());
+    assert!(machine.step_back());
+    assert!(machine.step_back());
+    assert!(!machine.step_back());
+    assert_eq!(8, machine.steps());
+    machine.step().unwrap();
+    machine.set_history(0);
+    assert_eq!(0, machine.history_len());
+    assert!(!machine.step_back());
+}
+
+#[test]
+fn undo_self_modifying_code() {
+    // The store replaces `out_number r3` by an `exit`
+    let program = assemble(
+        "
+        loadimm r1 <- #7
+        loadimm r3 <- #11
+        store [r3] <- r1
+        out_number r3
+        exit
+    ",
+    )
+    .unwrap();
+    let mut machine = Machine::new(&program).unwrap();
+    machine.set_history(10);
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert!(output.is_empty());
+
+    // Skip the store after rewinding: the original instruction runs
+    assert!(machine.run_back_to(8));
+    assert_eq!(program, machine.memory()[..program.len()]);
+    machine.set_reg(0, 11).unwrap();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"11", &output[..]);
+}
@@ -0,0 +1,182 @@
+use interpreter::{BinaryOp, Condition, Instruction};
+
+#[test]
+fn decode_each_opcode() {
+    let cases = [
+        (
+            &[1, 1, 2, 3][..],
+            Instruction::MoveIf {
+                rd: 1,
+                rs: 2,
+                rc: 3,
+            },
+        ),
+        (&[2, 2, 10], Instruction::Store { ra: 2, rs: 10 }),
+        (&[3, 11, 3], Instruction::Load { rd: 11, ra: 3 }),
+        (
+            &[4, 2, 0x00, 0x10],
+            Instruction::LoadImm { rd: 2, imm: 4096 },
+        ),
+        (&[4, 3, 0xfc, 0xff], Instruction::LoadImm { rd: 3, imm: -4 }),
+        (
+            &[5, 2, 2, 3],
+            Instruction::Sub {
+                rd: 2,
+                rs1: 2,
+                rs2: 3,
+            },
+        ),
+        (&[6, 3], Instruction::Out { rs: 3 }),
+        (&[7], Instruction::Exit),
+        (&[8, 15], Instruction::OutNumber { rs: 15 }),
+        (&[9, 4], Instruction::In { rd: 4 }),
+        (&[10, 5], Instruction::InNumber { rd: 5 }),
+        (
+            &[11, 1, 2, 3],
+            Instruction::Binary {
+                op: BinaryOp::Add,
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+            },
+        ),
+        (&[20, 4, 5], Instruction::Not { rd: 4, rs: 5 }),
+        (&[24, 3], Instruction::Push { rs: 3 }),
+        (&[25, 4], Instruction::Pop { rd: 4 }),
+        (&[26, 0x34, 0x12], Instruction::Call { target: 0x1234 }),
+        (&[27], Instruction::Ret),
+        (&[35], Instruction::Iret),
+        (
+            &[23, 1, 2, 3],
+            Instruction::Binary {
+                op: BinaryOp::Sar,
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+            },
+        ),
+    ];
+    for (bytes, expected) in cases {
+        // Trailing bytes belong to the next instruction and are ignored
+        let mut code = bytes.to_vec();
+        code.push(7);
+        assert_eq!((expected, bytes.len()), Instruction::decode(&code).unwrap());
+        assert_eq!(bytes, &expected.encode()[..]);
+        assert_eq!(bytes.len(), expected.size());
+    }
+}
+
+#[test]
+fn refuse_invalid_encodings() {
+    // Unknown opcodes
+    assert!(Instruction::decode(&[]).is_err());
+    assert!(Instruction::decode(&[0, 7]).is_err());
+    assert!(Instruction::decode(&[36, 7]).is_err());
+    // Truncated instruction
+    assert!(Instruction::decode(&[5, 1, 1]).is_err());
+    // Register above 15
+    assert!(Instruction::decode(&[6, 16]).is_err());
+    assert!(Instruction::decode(&[1, 1, 1, 100]).is_err());
+}
+
+#[test]
+fn display_as_listing() {
+    let cases = [
+        (
+            Instruction::MoveIf {
+                rd: 0,
+                rs: 8,
+                rc: 11,
+            },
+            "move r0 <- r8 if r11 != 0",
+        ),
+        (Instruction::Store { ra: 2, rs: 10 }, "store [r2] <- r10"),
+        (Instruction::Load { rd: 11, ra: 3 }, "load r11 <- [r3]"),
+        (Instruction::LoadImm { rd: 3, imm: -4 }, "loadimm r3 <- #-4"),
+        (
+            Instruction::Sub {
+                rd: 2,
+                rs1: 2,
+                rs2: 3,
+            },
+            "sub r2 <- r2 - r3",
+        ),
+        (Instruction::Out { rs: 3 }, "out r3"),
+        (Instruction::Exit, "exit"),
+        (Instruction::OutNumber { rs: 11 }, "out_number r11"),
+        (Instruction::In { rd: 3 }, "in r3"),
+        (Instruction::InNumber { rd: 3 }, "in_number r3"),
+        (
+            Instruction::Binary {
+                op: BinaryOp::Remu,
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+            },
+            "remu r1 <- r2 % r3",
+        ),
+        (Instruction::Not { rd: 1, rs: 2 }, "not r1 <- ~r2"),
+        (Instruction::Push { rs: 3 }, "push r3"),
+        (Instruction::Pop { rd: 3 }, "pop r3"),
+        (Instruction::Call { target: 92 }, "call #92"),
+        (Instruction::Ret, "ret"),
+        (Instruction::Iret, "iret"),
+        (
+            Instruction::Binary {
+                op: BinaryOp::Seq,
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+            },
+            "seq r1 <- r2 == r3",
+        ),
+        (
+            Instruction::Branch {
+                cond: Condition::Ge,
+                rs1: 1,
+                rs2: 2,
+                offset: -8,
+            },
+            "bge r1, r2, #-8",
+        ),
+    ];
+    for (instruction, text) in cases {
+        assert_eq!(text, instruction.to_string());
+    }
+}
+
+#[test]
+fn opcodes_of_binary_operations() {
+    for op in BinaryOp::ALL {
+        let bytes = [op.opcode(), 1, 2, 3];
+        let instruction = Instruction::Binary {
+            op,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+        assert_eq!((instruction, 4), Instruction::decode(&bytes).unwrap());
+    }
+}
+
+#[test]
+fn opcodes_of_branches() {
+    for cond in Condition::ALL {
+        let bytes = [cond.opcode(), 1, 2, 12, 0];
+        let instruction = Instruction::Branch {
+            cond,
+            rs1: 1,
+            rs2: 2,
+            offset: 12,
+        };
+        assert_eq!((instruction, 5), Instruction::decode(&bytes).unwrap());
+    }
+}
+
+#[test]
+fn size_of_opcodes() {
+    for opcode in 0..=u8::MAX {
+        let decoded = Instruction::decode(&[opcode, 0, 0, 0, 0]).ok();
+        assert_eq!(decoded.map(|(_, size)| size), Instruction::size_of(opcode));
+    }
+}
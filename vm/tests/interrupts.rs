@@ -0,0 +1,159 @@
+use interpreter::{Error, INTERRUPT_CONTROLLER_ADDRESS, Machine, Random, assemble};
+use std::io;
+
+/// Main loop counting in r1 forever, preempted every 10 instructions by a
+/// handler counting interrupts in r5.
+const PREEMPTED: &str = "
+    loadimm r3 <- #1
+    loadimm r4 <- #0x1100       ; interrupt controller
+    loadimm r6 <- #handler
+    loadimm r7 <- #4
+    add r7 <- r4 + r7
+    store [r7] <- r6            ; vector
+    loadimm r6 <- #10
+    loadimm r7 <- #12
+    add r7 <- r4 + r7
+    store [r7] <- r6            ; timer period
+    store [r4] <- r3            ; enable
+  loop:
+    add r1 <- r1 + r3
+    loadimm r0 <- #loop
+  handler:
+    add r5 <- r5 + r3
+    iret
+";
+
+fn preempted() -> Machine {
+    let mut machine = Machine::new(&assemble(PREEMPTED).unwrap()).unwrap();
+    machine
+        .map_interrupt_controller(INTERRUPT_CONTROLLER_ADDRESS)
+        .unwrap();
+    machine
+}
+
+#[test]
+fn timer_preempts_program() {
+    let mut machine = preempted();
+    // The timer is set up by the 10th instruction and enabled by the 11th
+    machine.run_for(11).unwrap();
+    assert!(machine.interrupts().enabled());
+    assert_eq!(10, machine.interrupts().period());
+    machine.run_for(8).unwrap();
+    let handler = assemble(PREEMPTED).unwrap().len() as u32 - 5;
+    assert_ne!(handler, machine.regs()[0]);
+    // The 10th instruction after setting the period fires the timer
+    machine.run_for(1).unwrap();
+    assert_eq!(handler, machine.regs()[0]);
+    assert_eq!(0, machine.regs()[5]);
+    assert!(!machine.interrupts().enabled());
+    let saved_ip = machine.interrupts().saved_ip();
+    machine.run_for(2).unwrap();
+    assert_eq!(1, machine.regs()[5]);
+    assert_eq!(saved_ip, machine.regs()[0]);
+    assert!(machine.interrupts().enabled());
+}
+
+#[test]
+fn deterministic_preemption() {
+    let counts = || {
+        let mut machine = preempted();
+        machine.run_for(10_000).unwrap();
+        (machine.regs()[1], machine.regs()[5])
+    };
+    let (loops, interrupts) = counts();
+    assert_eq!((loops, interrupts), counts());
+    // Interrupts are taken after instructions 20, 30, ..., 10000, and the
+    // last handler did not run yet
+    assert_eq!(998, interrupts);
+    // The remaining instructions alternate between add and loadimm
+    assert_eq!((10_000 - 11 - 2 * interrupts).div_ceil(2), loops);
+}
+
+#[test]
+fn interrupt_stays_pending_while_disabled() {
+    // 0: store [r4] <- r6   ; timer period of 1
+    // 3: loadimm r0 <- #3
+    // 7: exit
+    let mut machine = Machine::new(&[2, 4, 6, 4, 0, 3, 0, 7]).unwrap();
+    machine.map_interrupt_controller(0x100).unwrap();
+    machine.set_reg(4, 0x10c).unwrap();
+    machine.set_reg(6, 1).unwrap();
+    machine.run_for(5).unwrap();
+    assert!(machine.interrupts().pending());
+    assert_eq!(3, machine.regs()[0]);
+    // Handler at 7 (exit), enabled from outside through the registers
+    machine.set_reg(0, 0).unwrap();
+    machine.set_reg(4, 0x104).unwrap();
+    machine.set_reg(6, 7).unwrap();
+    machine.step().unwrap();
+    machine.set_reg(4, 0x100).unwrap();
+    machine.set_reg(6, 1).unwrap();
+    machine.set_reg(0, 0).unwrap();
+    machine.step().unwrap();
+    assert_eq!(7, machine.regs()[0]);
+    assert_eq!(3, machine.interrupts().saved_ip());
+    assert!(!machine.interrupts().pending());
+}
+
+#[test]
+fn no_interrupt_without_controller() {
+    let mut machine = Machine::new(&assemble(PREEMPTED).unwrap()).unwrap();
+    // The registers are plain memory beyond the end
+    let error = machine.run_for(100).unwrap_err();
+    assert!(matches!(error.cause(), Error::MemoryOverflow { .. }));
+}
+
+#[test]
+fn iret_without_controller() {
+    // 0: iret
+    let mut machine = Machine::new(&[35]).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(
+        error.cause(),
+        Error::InstructionError { opcode: 35 }
+    ));
+}
+
+#[test]
+fn iret_outside_handler() {
+    // 0: iret
+    let mut machine = Machine::new(&[35]).unwrap();
+    machine.map_interrupt_controller(0x100).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(
+        error.cause(),
+        Error::InstructionError { opcode: 35 }
+    ));
+    assert!(!machine.interrupts().enabled());
+}
+
+#[test]
+fn read_controller_registers() {
+    // 0: load r1 <- [r4]
+    // 3: exit
+    let mut machine = Machine::new(&[3, 1, 4, 7]).unwrap();
+    machine.map_interrupt_controller(0x100).unwrap();
+    machine.set_reg(4, 0x110).unwrap();
+    machine.run_on(&mut io::sink()).unwrap();
+    assert_eq!(0, machine.regs()[1]);
+
+    // 0: store [r4] <- r4
+    let mut machine = Machine::new(&[2, 4, 4]).unwrap();
+    machine.map_interrupt_controller(0x100).unwrap();
+    machine.set_reg(4, 0x110).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::UnsupportedAccess));
+}
+
+#[test]
+fn refuse_overlapping_controller() {
+    let mut machine = Machine::new(&[]).unwrap();
+    machine.map_device(0x100, Box::new(Random::new(1))).unwrap();
+    assert!(matches!(
+        machine.map_interrupt_controller(0xf0),
+        Err(Error::DeviceOverlap { address: 0xf0 })
+    ));
+    machine.map_interrupt_controller(0x104).unwrap();
+    assert!(machine.map_interrupt_controller(0x200).is_err());
+    assert!(machine.map_device(0x110, Box::new(Random::new(1))).is_err());
+}
@@ -0,0 +1,64 @@
+use interpreter::{Limits, Machine, Outcome};
+use std::time::{Duration, Instant};
+
+// 0: loadimm r0 <- #0
+const SPIN: [u8; 4] = [4, 0, 0, 0];
+
+fn run_limited(machine: &mut Machine, limits: Limits) -> Outcome {
+    machine
+        .run_limited(&mut std::io::empty(), &mut Vec::new(), limits)
+        .unwrap()
+}
+
+#[test]
+fn exhaust_budget() {
+    let mut machine = Machine::new(&SPIN).unwrap();
+    assert_eq!(
+        Outcome::BudgetExhausted { steps: 1000 },
+        machine.run_for(1000).unwrap()
+    );
+    assert_eq!(1000, machine.steps());
+    // The machine can be resumed with a new budget
+    assert_eq!(
+        Outcome::BudgetExhausted { steps: 10 },
+        machine.run_for(10).unwrap()
+    );
+    assert_eq!(1010, machine.steps());
+}
+
+#[test]
+fn exit_within_budget() {
+    let mut machine = Machine::new(include_bytes!("fact.bin")).unwrap();
+    machine.set_reg(10, 5).unwrap();
+    let limits = Limits {
+        max_steps: Some(100_000),
+        deadline: None,
+    };
+    let Outcome::Exited { steps } = run_limited(&mut machine, limits) else {
+        panic!()
+    };
+    assert_eq!(steps, machine.steps());
+    assert_eq!(120, machine.regs()[11]);
+
+    // Exactly the needed budget is enough
+    let mut machine = Machine::new(include_bytes!("fact.bin")).unwrap();
+    machine.set_reg(10, 5).unwrap();
+    assert_eq!(Outcome::Exited { steps }, machine.run_for(steps).unwrap());
+}
+
+#[test]
+fn time_out() {
+    let mut machine = Machine::new(&SPIN).unwrap();
+    let start = Instant::now();
+    let outcome = machine
+        .run_until(start + Duration::from_millis(50))
+        .unwrap();
+    assert!(matches!(outcome, Outcome::TimedOut { steps } if steps > 0));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn errors_are_not_outcomes() {
+    let mut machine = Machine::new(&[0]).unwrap();
+    assert!(machine.run_for(10).is_err());
+}
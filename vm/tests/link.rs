@@ -0,0 +1,189 @@
+use interpreter::{Error, MachineBuilder, Object, RelocationKind, assemble_object, link, stdlib};
+
+const MAIN: &str = "
+    _start:
+      loadimm r2 <- #4096
+      loadimm r10 <- #message
+      loadimm r11 <- #6
+      call #print
+      loadimm r11 <- #6
+      loadimm r12 <- #7
+      call #mult
+      move r10 <- r11 if r12 != 0
+      call #print_number
+      exit
+    message:
+      b'6*7 = '
+";
+
+fn object(source: &str) -> Object {
+    assemble_object(source).unwrap()
+}
+
+fn run(program: &Object) -> Vec<u8> {
+    let mut machine = MachineBuilder::new().load(&program.encode()).unwrap();
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    output
+}
+
+#[test]
+fn link_with_stdlib() {
+    let main = object(MAIN);
+    assert_eq!(
+        ["mult", "print", "print_number"],
+        main.undefined().into_iter().collect::<Vec<_>>()[..]
+    );
+    let program = link(&[main], &stdlib()).unwrap();
+    assert!(program.relocations.is_empty());
+    assert_eq!(0, program.entry);
+    assert_eq!(b"6*7 = 42", &run(&program)[..]);
+}
+
+#[test]
+fn library_objects_linked_on_demand() {
+    let main = object(
+        "
+          loadimm r2 <- #4096
+          loadimm r10 <- #42
+          call #print_number
+          exit
+        ",
+    );
+    let program = link(&[main], &stdlib()).unwrap();
+    assert!(program.symbols.contains_key("print_number"));
+    assert!(!program.symbols.contains_key("print"));
+    assert!(!program.symbols.contains_key("mult"));
+    assert_eq!(b"42", &run(&program)[..]);
+}
+
+#[test]
+fn modules_laid_out_in_order() {
+    let main = object(
+        "
+          loadimm r2 <- #4096
+          loadimm r10 <- #value
+          load r10 <- [r10]
+          call #show
+          exit
+        ",
+    );
+    let show = object(
+        "
+        show:
+          out_number r10
+          ret
+        value:
+          b'\\x07'
+        ",
+    );
+    let program = link(&[main.clone(), show.clone()], &[]).unwrap();
+    let start = main.image().len();
+    assert_eq!(Some(&start), program.symbols.get("show"));
+    assert_eq!(show.image(), program.image()[start..]);
+    assert_eq!(b"7", &run(&program)[..]);
+}
+
+#[test]
+fn local_symbols_take_precedence() {
+    let main = object(
+        "
+        _start:
+          loadimm r2 <- #4096
+          call #helper
+          call #show
+          exit
+        helper:
+          loadimm r10 <- #1
+          ret
+        ",
+    );
+    let show = object(
+        "
+        show:
+          call #helper
+          out_number r10
+          ret
+        helper:
+          loadimm r10 <- #2
+          ret
+        ",
+    );
+    let program = link(&[main.clone(), show], &[]).unwrap();
+    assert_eq!(main.symbols["helper"], program.symbols["helper"]);
+    assert_eq!(b"2", &run(&program)[..]);
+}
+
+#[test]
+fn undefined_symbol() {
+    let main = object("call #missing\nexit");
+    assert!(matches!(
+        link(std::slice::from_ref(&main), &stdlib()),
+        Err(Error::UndefinedSymbol(symbol)) if symbol == "missing"
+    ));
+    assert!(matches!(
+        MachineBuilder::new().load(&main.encode()),
+        Err(Error::UndefinedSymbol(symbol)) if symbol == "missing"
+    ));
+}
+
+#[test]
+fn ambiguous_symbol() {
+    let main = object("call #show\nexit");
+    let show = object("show:\nret");
+    assert!(matches!(
+        link(&[main, show.clone(), show], &[]),
+        Err(Error::AmbiguousSymbol(symbol)) if symbol == "show"
+    ));
+}
+
+#[test]
+fn relocation_overflow() {
+    let main = object("loadimm r10 <- #far\nexit");
+    let mut far = object("far:\nexit");
+    far.sections[0].address = 40_000;
+    far.symbols.insert("far".to_owned(), 40_000);
+    assert_eq!(
+        "address of `far` does not fit in the instruction at address 0",
+        link(&[main, far], &[]).unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn relocations_with_addend() {
+    let main = object(
+        "
+          loadimm r2 <- #4096
+          loadimm r10 <- #greeting+3
+          loadimm r11 <- #2
+          call #print
+          exit
+        ",
+    );
+    let greeting = object("greeting:\nb'hello'");
+    let program = link(&[main, greeting], &stdlib()).unwrap();
+    assert_eq!(b"lo", &run(&program)[..]);
+}
+
+#[test]
+fn relocate_data_words() {
+    let main = object(
+        "
+          loadimm r2 <- #4096
+          loadimm r10 <- #table
+          load r10 <- [r10]
+          loadimm r11 <- #2
+          call #print
+          exit
+        table:
+          .word greeting+1
+        ",
+    );
+    assert_eq!(
+        Some(&RelocationKind::Word),
+        main.relocations.last().map(|relocation| &relocation.kind)
+    );
+    let greeting = object("greeting:\n.string \"hello\"");
+    let program = link(&[main, greeting], &stdlib()).unwrap();
+    assert_eq!(b"el", &run(&program)[..]);
+}
@@ -0,0 +1,183 @@
+use interpreter::{
+    Error, FileFormat, ISA_VERSION, Labels, Machine, OBJECT_VERSION, Object, Relocation,
+    RelocationKind, Section, SectionKind, assemble, assemble_object,
+};
+
+const PROGRAM: &str = "
+    message:
+      b'hi'
+    _start:
+      loadimm r10 <- #message
+      load r1 <- [r10]
+      out r1
+      exit
+";
+
+#[test]
+fn assemble_sections_and_symbols() {
+    let object = assemble_object(PROGRAM).unwrap();
+    assert_eq!(2, object.entry);
+    assert_eq!(
+        vec![
+            Section {
+                kind: SectionKind::Data,
+                address: 0,
+                bytes: b"hi".to_vec()
+            },
+            Section {
+                kind: SectionKind::Code,
+                address: 2,
+                bytes: assemble(PROGRAM).unwrap()[2..].to_vec()
+            }
+        ],
+        object.sections
+    );
+    assert_eq!(
+        Labels::from([("_start".to_owned(), 2), ("message".to_owned(), 0)]),
+        object.symbols
+    );
+    assert_eq!(assemble(PROGRAM).unwrap(), object.image());
+}
+
+#[test]
+fn encode_and_decode() {
+    let object = assemble_object(PROGRAM).unwrap();
+    let file = object.encode();
+    assert_eq!(b"VMOB", &file[..4]);
+    assert_eq!(OBJECT_VERSION.to_le_bytes(), file[4..8]);
+    assert_eq!(ISA_VERSION.to_le_bytes(), file[8..12]);
+    assert_eq!(object, Object::decode(&file).unwrap());
+    assert_eq!(Some(FileFormat::Object), FileFormat::detect(&file));
+
+    let external = assemble_object("call #print\nexit").unwrap();
+    assert_eq!(
+        vec![Relocation {
+            address: 0,
+            kind: RelocationKind::Call,
+            symbol: "print".to_owned(),
+            addend: 0
+        }],
+        external.relocations
+    );
+    assert_eq!(external, Object::decode(&external.encode()).unwrap());
+}
+
+fn invalid(file: &[u8]) -> String {
+    match Object::decode(file) {
+        Err(error @ Error::InvalidObject(_)) => error.to_string(),
+        other => panic!("{other:?}"),
+    }
+}
+
+// Fix the CRC-32 after altering an object file
+fn seal(mut file: Vec<u8>) -> Vec<u8> {
+    file.truncate(file.len() - 4);
+    let mut crc = !0u32;
+    for &byte in &file {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    file.extend((!crc).to_le_bytes());
+    file
+}
+
+#[test]
+fn reject_invalid_objects() {
+    let file = assemble_object(PROGRAM).unwrap().encode();
+    assert_eq!(
+        "invalid object file: not an object file",
+        invalid(b"\x04\x01")
+    );
+    assert_eq!(
+        "invalid object file: checksum mismatch",
+        invalid(&file[..20])
+    );
+    let mut corrupted = file.clone();
+    corrupted[30] ^= 1;
+    assert_eq!(
+        "invalid object file: checksum mismatch",
+        invalid(&corrupted)
+    );
+
+    let mut version = file.clone();
+    version[4] = 2;
+    assert_eq!(
+        "invalid object file: unsupported version 2",
+        invalid(&seal(version))
+    );
+    let mut isa = file.clone();
+    isa[8] = 2;
+    assert_eq!(
+        "invalid object file: unsupported ISA version 2",
+        invalid(&seal(isa))
+    );
+    let mut truncated = file.clone();
+    truncated.truncate(24);
+    assert_eq!("invalid object file: truncated", invalid(&seal(truncated)));
+
+    let overlapping = Object {
+        sections: vec![
+            Section {
+                kind: SectionKind::Code,
+                address: 0,
+                bytes: vec![7; 4],
+            },
+            Section {
+                kind: SectionKind::Data,
+                address: 3,
+                bytes: vec![0; 4],
+            },
+        ],
+        ..Object::default()
+    };
+    assert_eq!(
+        "invalid object file: overlapping sections at address 3",
+        invalid(&overlapping.encode())
+    );
+}
+
+#[test]
+fn load_objects_and_raw_images() {
+    let object = assemble_object(PROGRAM).unwrap();
+    let mut machine = Machine::builder().load(&object.encode()).unwrap();
+    assert_eq!(2, machine.regs()[0]);
+    let mut output = Vec::new();
+    machine.run_on(&mut output).unwrap();
+    assert_eq!(b"h", &output[..]);
+
+    // Raw images start at address 0, here in the data
+    let image = assemble(PROGRAM).unwrap();
+    assert_eq!(Some(FileFormat::Raw), FileFormat::detect(&image[2..]));
+    let machine = Machine::builder().load(&image).unwrap();
+    assert_eq!(0, machine.regs()[0]);
+    assert_eq!(image, machine.memory()[..image.len()]);
+
+    assert_eq!(None, FileFormat::detect(b"[package]"));
+    assert_eq!(None, FileFormat::detect(b""));
+}
+
+#[test]
+fn reject_sections_beyond_memory() {
+    let far = Object {
+        sections: vec![Section {
+            kind: SectionKind::Code,
+            address: 0xffff_0000,
+            bytes: vec![7],
+        }],
+        ..Object::default()
+    };
+    assert_eq!(0xffff_0001, far.size());
+    assert!(matches!(
+        Machine::builder().load(&far.encode()),
+        Err(Error::ProgramTooLarge {
+            size: 0xffff_0001,
+            memory_size: 4096
+        })
+    ));
+}
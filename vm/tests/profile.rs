@@ -0,0 +1,84 @@
+use interpreter::{Block, Function, Machine, Profile, assemble};
+
+#[test]
+fn count_calls_and_blocks() {
+    let program = assemble(
+        "
+        loadimm r2 <- #4096
+        loadimm r10 <- #3
+        loadimm r3 <- #1
+      loop:
+        call #show
+        sub r10 <- r10 - r3
+        bne r10, r4, #loop
+        exit
+      show:
+        out_number r10
+        ret
+    ",
+    )
+    .unwrap();
+    let mut machine = Machine::new(&program).unwrap();
+    machine.set_profile(Some(Profile::new()));
+    machine.run_on(&mut Vec::new()).unwrap();
+    let profile = machine.set_profile(None).unwrap();
+
+    assert_eq!(3 + 3 * 5 + 1, profile.instructions());
+    assert_eq!(3, profile.count(12));
+    assert_eq!(1, profile.count(24));
+    assert_eq!(Some(&3), profile.kinds().get("call"));
+    assert_eq!(Some(&3), profile.kinds().get("bne"));
+    assert_eq!(None, profile.kinds().get("push"));
+    let blocks: Vec<_> = profile.blocks().into_iter().collect();
+    let block = |executions, instructions| Block {
+        executions,
+        instructions,
+    };
+    assert_eq!(
+        vec![
+            ((0, 12), block(1, 4)),
+            ((12, 12), block(2, 2)),
+            ((15, 19), block(2, 4)),
+            ((15, 24), block(1, 3)),
+            ((25, 27), block(3, 6)),
+        ],
+        blocks
+    );
+    let functions = profile.functions();
+    assert_eq!(
+        Some(&Function {
+            calls: 3,
+            own: 6,
+            total: 6
+        }),
+        functions.get(&25)
+    );
+    assert_eq!(
+        Some(&Function {
+            calls: 1,
+            own: 13,
+            total: 19
+        }),
+        functions.get(&0)
+    );
+}
+
+#[test]
+fn count_recursive_calls_once() {
+    let mut machine = Machine::new(include_bytes!("rfact.bin")).unwrap();
+    machine.set_reg(10, 5).unwrap();
+    machine.set_profile(Some(Profile::new()));
+    machine.run().unwrap();
+    let profile = machine.set_profile(None).unwrap();
+    let functions = profile.functions();
+
+    // rfact calls itself until reaching 1, and mult for every other level
+    let rfact = functions[&87];
+    assert_eq!(5, rfact.calls);
+    assert_eq!(4, functions[&24].calls);
+    // The program only calls rfact and exits
+    assert_eq!(profile.instructions() - 7, rfact.total);
+    assert_eq!(profile.instructions(), functions[&0].total);
+    let own: u64 = functions.values().map(|function| function.own).sum();
+    assert_eq!(profile.instructions(), own);
+}
@@ -0,0 +1,116 @@
+use interpreter::{
+    Device, Error, INTERRUPT_CONTROLLER_ADDRESS, Machine, Random, SNAPSHOT_VERSION, TickCounter,
+};
+use std::io;
+
+/// Run `machine` for at most `max_steps` instructions, collecting the output.
+fn run_for(machine: &mut Machine, max_steps: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    let limits = interpreter::Limits {
+        max_steps: Some(max_steps),
+        deadline: None,
+    };
+    machine
+        .run_limited(&mut io::empty(), &mut out, limits)
+        .unwrap();
+    out
+}
+
+#[test]
+fn resume_from_snapshot() {
+    let program = include_bytes!("../examples/99bottles.bin");
+    let mut machine = Machine::new(program).unwrap();
+    let full = run_for(&mut machine, u64::MAX);
+
+    let mut machine = Machine::new(program).unwrap();
+    let mut out = run_for(&mut machine, 1000);
+    let snapshot = machine.snapshot();
+    let mut resumed = Machine::new(&[]).unwrap();
+    resumed.restore(&snapshot).unwrap();
+    assert_eq!(machine.regs(), resumed.regs());
+    assert_eq!(machine.memory(), resumed.memory());
+    assert_eq!(1000, resumed.steps());
+    out.extend(run_for(&mut resumed, u64::MAX));
+    assert_eq!(full, out);
+}
+
+/// Machine with 256 bytes of memory, two devices and interrupts.
+fn with_devices(code: &[u8]) -> Machine {
+    let mut machine = Machine::builder().memory_size(256).build(code).unwrap();
+    machine.map_device(0x200, Box::new(Random::new(7))).unwrap();
+    machine
+        .map_device(0x300, Box::new(TickCounter::new()))
+        .unwrap();
+    machine
+        .map_interrupt_controller(INTERRUPT_CONTROLLER_ADDRESS)
+        .unwrap();
+    machine
+}
+
+#[test]
+fn save_device_and_interrupt_state() {
+    // 0: loadimm r0 <- #0 (forever), ticking the counter
+    let mut running = with_devices(&[4, 0, 0, 0]);
+    run_for(&mut running, 42);
+
+    let mut machine = Machine::new(&[]).unwrap();
+    assert!(machine.restore(&running.snapshot()).is_err());
+    let mut machine = with_devices(&[]);
+    machine.restore(&running.snapshot()).unwrap();
+    assert_eq!(42, machine.steps());
+    assert_eq!(256, machine.memory().len());
+    assert_eq!(running.snapshot(), machine.snapshot());
+}
+
+#[test]
+fn restore_device_states() {
+    let mut random = Random::new(7);
+    random.load(0).unwrap();
+    let mut copy = Random::new(1);
+    copy.restore(&random.save()).unwrap();
+    assert_eq!(random.load(0).unwrap(), copy.load(0).unwrap());
+
+    let mut ticks = TickCounter::new();
+    ticks.tick();
+    let mut copy = TickCounter::new();
+    copy.restore(&ticks.save()).unwrap();
+    assert_eq!(1, copy.load(0).unwrap());
+    assert!(copy.restore(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn refuse_invalid_snapshots() {
+    let mut machine = Machine::new(&[7]).unwrap();
+    let snapshot = machine.snapshot();
+    let invalid = |machine: &mut Machine, snapshot: &[u8]| {
+        matches!(machine.restore(snapshot), Err(Error::InvalidSnapshot(_)))
+    };
+
+    assert!(invalid(&mut machine, b"not a snapshot"));
+    assert!(invalid(&mut machine, &snapshot[..snapshot.len() - 1]));
+    let mut longer = snapshot.clone();
+    longer.push(0);
+    assert!(invalid(&mut machine, &longer));
+    let mut newer = snapshot.clone();
+    newer[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    let error = machine.restore(&newer).unwrap_err();
+    assert_eq!(
+        format!(
+            "invalid snapshot: unsupported version {}",
+            SNAPSHOT_VERSION + 1
+        ),
+        error.to_string()
+    );
+
+    // Devices must be mapped the same way
+    machine
+        .map_device(0x2000, Box::new(Random::new(1)))
+        .unwrap();
+    assert!(invalid(&mut machine, &snapshot));
+    let mut other = Machine::new(&[7]).unwrap();
+    other.map_device(0x3000, Box::new(Random::new(1))).unwrap();
+    assert!(invalid(&mut other, &machine.snapshot()));
+    // Nothing changed on failure
+    assert_eq!(7, other.memory()[0]);
+    assert_eq!(4096, other.memory().len());
+}
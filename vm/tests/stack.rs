@@ -0,0 +1,120 @@
+use interpreter::{Error, MEMORY_SIZE, Machine, assemble};
+
+#[test]
+fn push_and_pop() {
+    // 0: push r1
+    // 2: push r3
+    // 4: pop r4
+    // 6: pop r5
+    // 8: exit
+    let mut machine = Machine::new(&[24, 1, 24, 3, 25, 4, 25, 5, 7]).unwrap();
+    machine.set_reg(1, 0x0102_0304).unwrap();
+    machine.set_reg(2, MEMORY_SIZE as u32).unwrap();
+    machine.set_reg(3, 42).unwrap();
+    machine.step().unwrap();
+    assert_eq!(MEMORY_SIZE as u32 - 4, machine.regs()[2]);
+    assert_eq!([4, 3, 2, 1], machine.memory()[MEMORY_SIZE - 4..]);
+    machine.run().unwrap();
+    assert_eq!(42, machine.regs()[4]);
+    assert_eq!(0x0102_0304, machine.regs()[5]);
+    assert_eq!(MEMORY_SIZE as u32, machine.regs()[2]);
+}
+
+#[test]
+fn call_and_ret() {
+    // 0: call #5
+    // 3: exit
+    // 4: exit
+    // 5: ret
+    let mut machine = Machine::new(&[26, 5, 0, 7, 7, 27]).unwrap();
+    machine.set_reg(2, 64).unwrap();
+    machine.step().unwrap();
+    assert_eq!(5, machine.regs()[0]);
+    assert_eq!(60, machine.regs()[2]);
+    // The return address is the one of the instruction following the call
+    assert_eq!([3, 0, 0, 0], machine.memory()[60..64]);
+    machine.step().unwrap();
+    assert_eq!(3, machine.regs()[0]);
+    assert_eq!(64, machine.regs()[2]);
+}
+
+#[test]
+fn stack_overflow() {
+    // 0: push r1
+    let mut machine = Machine::new(&[24, 1]).unwrap();
+    machine.set_reg(2, 3).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::StackOverflow { sp: 3 }));
+    assert_eq!(3, machine.regs()[2]);
+
+    // 0: call #0
+    let mut machine = Machine::new(&[26, 0, 0]).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::StackOverflow { sp: 0 }));
+}
+
+#[test]
+fn stack_underflow() {
+    // 0: pop r1
+    let mut machine = Machine::new(&[25, 1]).unwrap();
+    machine.set_reg(2, MEMORY_SIZE as u32).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(
+        error.cause(),
+        Error::StackUnderflow { sp } if *sp == MEMORY_SIZE
+    ));
+    assert_eq!(
+        "at address 0000 (opcode 25): stack underflow (r2 = 4096)",
+        error.to_string()
+    );
+
+    // 0: ret
+    let mut machine = Machine::new(&[27]).unwrap();
+    machine.set_reg(2, MEMORY_SIZE as u32 - 2).unwrap();
+    let error = machine.step().unwrap_err();
+    assert!(matches!(error.cause(), Error::StackUnderflow { .. }));
+}
+
+#[test]
+fn pop_into_stack_pointer() {
+    // 0: pop r2
+    let mut machine = Machine::new(&[25, 2]).unwrap();
+    machine.set_reg(2, 16).unwrap();
+    machine.step().unwrap();
+    // The popped value wins over the incremented stack pointer
+    assert_eq!(0, machine.regs()[2]);
+}
+
+#[test]
+fn recursive_function() {
+    // Same as tests/rfact.dis, using native calls
+    let program = assemble(
+        "
+        loadimm r2 <- #4096
+        call #rfact
+        out_number r1
+        exit
+      rfact:
+        loadimm r1 <- #1
+        loadimm r8 <- #recurse
+        move r0 <- r8 if r10 != 0
+        ret
+      recurse:
+        push r10
+        sub r10 <- r10 - r1
+        call #rfact
+        pop r10
+        mul r1 <- r1 * r10
+        ret
+    ",
+    )
+    .unwrap();
+    for (n, expected) in [(0, "1"), (1, "1"), (5, "120"), (10, "3628800")] {
+        let mut machine = Machine::new(&program).unwrap();
+        machine.set_reg(10, n).unwrap();
+        let mut out = Vec::new();
+        machine.run_on(&mut out).unwrap();
+        assert_eq!(expected.as_bytes(), &out[..]);
+        assert_eq!(4096, machine.regs()[2]);
+    }
+}
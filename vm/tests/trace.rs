@@ -0,0 +1,62 @@
+use interpreter::{Instruction, Machine, Trace, TraceRecord};
+
+fn run_traced(code: &[u8]) -> Vec<TraceRecord> {
+    let mut machine = Machine::new(code).unwrap();
+    machine.set_trace(Some(Trace::Records(Vec::new())));
+    machine.run_on(&mut Vec::new()).unwrap();
+    match machine.set_trace(None) {
+        Some(Trace::Records(records)) => records,
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn record_register_changes() {
+    // 0: loadimm r1 <- #-2
+    // 4: loadimm r1 <- #-2
+    // 8: exit
+    let records = run_traced(&[4, 1, 0xfe, 0xff, 4, 1, 0xfe, 0xff, 7]);
+    assert_eq!(3, records.len());
+    assert_eq!(
+        TraceRecord {
+            address: 0,
+            instruction: Instruction::LoadImm { rd: 1, imm: -2 },
+            registers: vec![(1, 0xffff_fffe)],
+            memory: vec![],
+        },
+        records[0]
+    );
+    // Unchanged registers are not listed
+    assert!(records[1].registers.is_empty());
+    assert_eq!(8, records[2].address);
+}
+
+#[test]
+fn record_jumps_and_memory_writes() {
+    // 0: loadimm r1 <- #4096-4
+    // 4: store [r1] <- r1
+    // 7: loadimm r0 <- #12
+    // 11: invalid
+    // 12: exit
+    let records = run_traced(&[4, 1, 0xfc, 0x0f, 2, 1, 1, 4, 0, 12, 0, 0, 7]);
+    assert_eq!(
+        vec![(4092, 0xfc), (4093, 0x0f), (4094, 0), (4095, 0)],
+        records[1].memory
+    );
+    assert_eq!(vec![(0, 12)], records[2].registers);
+    assert_eq!(12, records[3].address);
+}
+
+#[test]
+fn format_records() {
+    let record = TraceRecord {
+        address: 12,
+        instruction: Instruction::Store { ra: 2, rs: 10 },
+        registers: vec![(0, 42), (3, 0xffff_fffc)],
+        memory: vec![(4092, 1), (4093, 2), (100, 3)],
+    };
+    assert_eq!(
+        "0012   store [r2] <- r10 ; r0 = 0x0000002a r3 = 0xfffffffc [4092] = 01 02 [100] = 03",
+        record.to_string()
+    );
+}